@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::CacheAction;
+use crate::i18n::{Msg, tr};
+use crate::stat::{Stat, stat_path};
+use crate::types::EntrySize;
+use crate::utils::format_size;
+
+const CACHE_VERSION: u32 = 3;
+/// Shards not used for this long are deleted
+const SHARD_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// One line of a shard file: the size of everything below a directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedDir {
+    dev: u64,
+    ino: u64,
+    path: PathBuf,
+    stamp: [i64; 4],
+    apparent: u64,
+    allocated: u64,
+    /// When the size was counted, in seconds since the epoch
+    counted: u64,
+}
+
+/// On-disk cache of `--deep` directory sizes, keyed by device and inode, so
+/// that a subtree that did not change is not walked again. A size is
+/// trusted while the directory's mtime and ctime match and for at most
+/// `--cache-max-age`. Changes further down and files rewritten in place do
+/// not touch the directory, so the age limit is what bounds how stale a
+/// size can get; `--refresh` recounts everything.
+///
+/// Each listed directory has its own shard, a JSON Lines file named after
+/// its device and inode, so a run only reads the tree it lists. New entries
+/// are appended; a shard is rewritten only once most of its lines are
+/// outdated.
+pub struct SizeCache {
+    shard: Option<PathBuf>,
+    /// The listed directory as given and as resolved, to store absolute
+    /// paths for `ils cache prune` without resolving every directory
+    given_root: PathBuf,
+    root: PathBuf,
+    dirs: HashMap<(u64, u64), CachedDir>,
+    /// Lines in the shard, counting outdated ones
+    lines: usize,
+    fresh: Mutex<Vec<CachedDir>>,
+    /// Ignore existing entries but still store fresh ones
+    refresh: bool,
+    max_age: Duration,
+}
+
+impl SizeCache {
+    pub fn load(root: &Path, root_stat: &Stat, refresh: bool, max_age: Duration) -> SizeCache {
+        let shard = shard_dir().map(|dir| dir.join(shard_name(root_stat)));
+        let (dirs, lines) = match &shard {
+            Some(shard) if !refresh => read_shard(shard),
+            _ => (HashMap::new(), 0),
+        };
+
+        SizeCache {
+            shard,
+            given_root: root.to_path_buf(),
+            root: fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf()),
+            dirs,
+            lines,
+            fresh: Mutex::default(),
+            refresh,
+            max_age,
+        }
+    }
+
+    pub fn get(&self, stat: &Stat) -> Option<EntrySize> {
+        if self.refresh {
+            return None;
+        }
+        let cached = self.dirs.get(&stat.inode())?;
+        let age = now().saturating_sub(cached.counted);
+        (cached.stamp == stat.stamp && age < self.max_age.as_secs()).then(|| EntrySize {
+            apparent: cached.apparent,
+            allocated: cached.allocated,
+            ..EntrySize::default()
+        })
+    }
+
+    /// Stores the size of everything below `path`. Only sizes that do not
+    /// depend on the rest of the walk belong here, see `get_dir_size`.
+    pub fn put(&self, path: &Path, stat: &Stat, size: &EntrySize) {
+        let path = match path.strip_prefix(&self.given_root) {
+            Ok(rest) => self.root.join(rest),
+            Err(_) => path.to_path_buf(),
+        };
+        self.fresh.lock().unwrap().push(CachedDir {
+            dev: stat.dev,
+            ino: stat.ino,
+            path,
+            stamp: stat.stamp,
+            apparent: size.apparent,
+            allocated: size.allocated,
+            counted: now(),
+        });
+    }
+
+    /// Appends the entries stored by this run, or rewrites the shard when
+    /// it has grown mostly stale, then deletes shards unused for
+    /// `SHARD_MAX_AGE`. Failures are ignored, the cache only ever saves
+    /// time.
+    pub fn save(self) {
+        let Some(shard) = &self.shard else {
+            return;
+        };
+        let fresh = self.fresh.into_inner().unwrap();
+        let stale = self.lines.saturating_sub(self.dirs.len());
+
+        let _ = if stale > self.dirs.len().max(100) || (self.refresh && !fresh.is_empty()) {
+            let mut dirs = if self.refresh {
+                read_shard(shard).0
+            } else {
+                self.dirs
+            };
+            for cached in fresh {
+                dirs.insert((cached.dev, cached.ino), cached);
+            }
+            write_shard(shard, dirs.values())
+        } else if !fresh.is_empty() {
+            append_to_shard(shard, &fresh)
+        } else {
+            // Mark the shard as used
+            OpenOptions::new()
+                .append(true)
+                .open(shard)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+        };
+        evict_unused_shards();
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+fn shard_name(root_stat: &Stat) -> String {
+    format!("{}-{}.jsonl", root_stat.dev, root_stat.ino)
+}
+
+/// Reads a shard, later lines replacing earlier ones for the same
+/// directory. Also returns the number of lines read.
+fn read_shard(shard: &Path) -> (HashMap<(u64, u64), CachedDir>, usize) {
+    let Ok(content) = fs::read_to_string(shard) else {
+        return (HashMap::new(), 0);
+    };
+    let mut dirs = HashMap::new();
+    let mut lines = 0;
+    for line in content.lines() {
+        lines += 1;
+        if let Ok(cached) = serde_json::from_str::<CachedDir>(line) {
+            dirs.insert((cached.dev, cached.ino), cached);
+        }
+    }
+    (dirs, lines)
+}
+
+fn append_to_shard(shard: &Path, entries: &[CachedDir]) -> io::Result<()> {
+    if let Some(dir) = shard.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut buffer = Vec::new();
+    for cached in entries {
+        serde_json::to_writer(&mut buffer, cached)?;
+        buffer.push(b'\n');
+    }
+    // A single append, so that concurrent runs do not interleave lines
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(shard)?
+        .write_all(&buffer)
+}
+
+fn write_shard<'a>(shard: &Path, entries: impl Iterator<Item = &'a CachedDir>) -> io::Result<()> {
+    if let Some(dir) = shard.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut buffer = Vec::new();
+    for cached in entries {
+        serde_json::to_writer(&mut buffer, cached)?;
+        buffer.push(b'\n');
+    }
+    // Write then rename so that a concurrent run never reads half a file
+    let tmp = shard.with_extension(format!("jsonl.{}", std::process::id()));
+    fs::write(&tmp, buffer)?;
+    fs::rename(&tmp, shard)
+}
+
+fn evict_unused_shards() {
+    let Some(dir) = shard_dir() else {
+        return;
+    };
+    // Caches written by older versions are never read again
+    if let Some(Ok(versions)) = dir.parent().map(fs::read_dir) {
+        for version in versions.flatten() {
+            let name = version.file_name();
+            if name.to_string_lossy().starts_with("sizes-v") && version.path() != dir {
+                let _ = fs::remove_dir_all(version.path());
+            }
+        }
+    }
+    let Ok(shards) = fs::read_dir(&dir) else {
+        return;
+    };
+    for shard in shards.flatten() {
+        let unused = shard
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > SHARD_MAX_AGE);
+        if unused {
+            let _ = fs::remove_file(shard.path());
+        }
+    }
+}
+
+fn shard_dir() -> Option<PathBuf> {
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(
+        cache_dir
+            .join("iyanls")
+            .join(format!("sizes-v{}", CACHE_VERSION)),
+    )
+}
+
+/// Runs `ils cache info`, `ils cache prune` or `ils cache clear`.
+pub fn run(action: &CacheAction) {
+    let Some(dir) = shard_dir() else {
+        eprintln!("{}", tr(Msg::CacheNoLocation).red());
+        return;
+    };
+    let shards: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match action {
+        CacheAction::Info => {
+            let entries: usize = shards.iter().map(|shard| read_shard(shard).0.len()).sum();
+            let size: u64 = shards
+                .iter()
+                .map(|shard| fs::metadata(shard).map_or(0, |meta| meta.len()))
+                .sum();
+            println!("{} {}", tr(Msg::CacheLocation), dir.display().bright_cyan());
+            println!(
+                "{} {} · {}",
+                tr(Msg::CacheEntries),
+                entries.bright_yellow(),
+                format_size(size, &Default::default()).trim_start()
+            );
+        }
+        CacheAction::Prune => {
+            let mut removed = 0;
+            for shard in &shards {
+                let (mut dirs, _) = read_shard(shard);
+                let before = dirs.len();
+                // Drop directories that are gone or whose inode now belongs
+                // to something else
+                dirs.retain(|key, cached| {
+                    stat_path(&cached.path, true).is_ok_and(|stat| stat.inode() == *key)
+                });
+                removed += before - dirs.len();
+                let result = if dirs.is_empty() {
+                    fs::remove_file(shard)
+                } else {
+                    write_shard(shard, dirs.values())
+                };
+                if let Err(e) = result {
+                    eprintln!("{}: {}", tr(Msg::CacheWriteFailed).red(), e);
+                    return;
+                }
+            }
+            println!("{} {}", tr(Msg::CachePruned), removed);
+        }
+        CacheAction::Clear => match fs::remove_dir_all(&dir) {
+            Ok(()) => println!("{}", tr(Msg::CacheCleared).green()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                println!("{}", tr(Msg::CacheCleared).green())
+            }
+            Err(e) => eprintln!("{}: {}", tr(Msg::CacheWriteFailed).red(), e),
+        },
+    }
+}
@@ -1,7 +1,14 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
-use crate::types::TimeFormat;
+use crate::types::{
+    BlockSize, GroupBy, Heatmap, Hyperlink, SizeBar, SizeMode, SizeUnits, Thresholds, TimeField,
+    TimeFormat, TimeStyle,
+};
+use crate::utils::{
+    parse_age_thresholds, parse_block_size, parse_duration, parse_size, parse_size_thresholds,
+    parse_time_format, parse_time_style,
+};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -11,17 +18,99 @@ use crate::types::TimeFormat;
     long_about = "Iyan's ls tool."
 )]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     pub path: Option<PathBuf>,
     #[arg(short, long, help = "Toggle deep processing", default_value = "false")]
     pub deep: bool,
+    #[arg(
+        long,
+        help = "With --deep, count every hard link instead of each file once"
+    )]
+    pub count_links: bool,
+    #[arg(
+        long,
+        help = "With --deep, skip directories on other filesystems (e.g. /proc, network mounts)"
+    )]
+    pub one_file_system: bool,
+    #[arg(long, help = "With --deep, follow symbolic links to directories")]
+    pub follow_symlinks: bool,
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Number of threads for reading metadata and sizes (1 scans sequentially)"
+    )]
+    pub threads: Option<u16>,
+    #[arg(
+        long,
+        help = "With --deep, recompute every directory size and update the cache"
+    )]
+    pub refresh: bool,
+    #[arg(
+        long,
+        conflicts_with = "refresh",
+        help = "With --deep, neither read nor write the size cache"
+    )]
+    pub no_cache: bool,
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        default_value = "1h",
+        help = "With --deep, recount cached directory sizes older than this (e.g. 10m, 1d)"
+    )]
+    pub cache_max_age: u64,
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        help = "With --deep, stop sizing after this long and show partial sizes (e.g. 30s, 2m)"
+    )]
+    pub timeout: Option<u64>,
     #[arg(
         short,
         long,
         help = "Filter files by pattern (matches filenames containing the pattern)"
     )]
     pub grab: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Only list files whose contents contain PATTERN, with the match count and first matching line"
+    )]
+    pub contains: Option<String>,
+    #[arg(
+        long,
+        requires = "contains",
+        help = "Treat the --contains pattern as a regular expression"
+    )]
+    pub regex: bool,
+    #[arg(
+        long,
+        requires = "contains",
+        help = "With --contains, also search binary files"
+    )]
+    pub binary: bool,
+    #[arg(
+        long,
+        requires = "contains",
+        help = "With --contains, also search files matched by .gitignore or .ignore"
+    )]
+    pub no_ignore: bool,
+    #[arg(
+        long,
+        requires = "contains",
+        value_parser = parse_size,
+        default_value = "10MB",
+        help = "With --contains, skip files larger than this"
+    )]
+    pub max_search_size: u64,
     #[arg(short, long, help = "Format output to JSON")]
     pub json: bool,
+    #[arg(
+        long,
+        conflicts_with = "json",
+        help = "Format output to CSV, with sizes in bytes and times in RFC 3339"
+    )]
+    pub csv: bool,
     #[arg(alias = "jsx", long, help = "Export JSON output to a file")]
     pub json_export: Option<PathBuf>,
     #[arg(short = 'n', long, help = "Hide line numbers")]
@@ -46,21 +135,75 @@ pub struct Args {
         long,
         value_enum,
         default_value = "local",
-        help = "Time format for dates (utc, local, unix, iso8601, rfc3339, utf, custom)"
+        help = "Time format for dates (utc, local, unix, iso8601, rfc3339, utf, custom, relative)"
     )]
     pub time_format: TimeFormat,
     #[arg(
         long,
         help = "Custom time format string (used with --time-format=custom)",
-        default_value = "%Y-%m-%d %H:%M:%S %Z"
+        default_value = "%Y-%m-%d %H:%M:%S %Z",
+        value_parser = parse_time_format
     )]
     pub custom_time_format: String,
     #[arg(
         long,
-        help = "Timezone for time display (e.g., US/Eastern, Europe/London, Asia/Tokyo)",
-        default_value = "UTC"
+        value_parser = parse_time_style,
+        help = "GNU ls time style, overrides --time-format (full-iso, long-iso, iso, locale, +FORMAT)"
+    )]
+    pub time_style: Option<TimeStyle>,
+    #[arg(
+        long,
+        help = "Timezone for time display (e.g., US/Eastern, Asia/Tokyo, +05:30); defaults to TZ or the system zone"
+    )]
+    pub timezone: Option<String>,
+    #[arg(
+        long,
+        help = "Number of units shown by --time-format=relative (e.g. 2 gives \"3 hours 5 min ago\")",
+        default_value = "1",
+        value_parser = clap::value_parser!(u8).range(1..=7)
+    )]
+    pub relative_precision: u8,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "si",
+        help = "Size units: powers of 1000 (si), powers of 1024 (iec) or exact bytes"
+    )]
+    pub size_units: SizeUnits,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "apparent",
+        help = "Size to list and sort by: file length (apparent), allocated blocks (disk) or both"
+    )]
+    pub size_mode: SizeMode,
+    #[arg(
+        long,
+        value_parser = parse_block_size,
+        help = "Show sizes in SIZE-byte blocks like GNU ls (e.g. K, M, MB, 1M, \'1)"
+    )]
+    pub block_size: Option<BlockSize>,
+    #[arg(
+        short = 'k',
+        long,
+        conflicts_with = "block_size",
+        help = "Show sizes as counts of 1024-byte blocks (same as --block-size=1K)"
+    )]
+    pub kibibytes: bool,
+    #[arg(
+        long,
+        help = "Number of decimals shown for KB/KiB and larger sizes",
+        value_parser = clap::value_parser!(u8).range(0..=6)
+    )]
+    pub size_precision: Option<u8>,
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "modified",
+        help = "Timestamps to show, comma-separated; the first one is used for sorting (modified, accessed, changed, created)"
     )]
-    pub timezone: String,
+    pub time_field: Vec<TimeField>,
     #[arg(long, help = "Toggle clock display", default_value = "false")]
     pub toggle_clock: bool,
     #[arg(short = 'U', long, help = "Sort files by newest modified to oldest")]
@@ -79,6 +222,100 @@ pub struct Args {
     pub dir_first: bool,
     #[arg(short = 'L', long, help = "Sort files by directory last")]
     pub dir_last: bool,
+    #[arg(
+        long,
+        value_parser = parse_size,
+        default_value = "128MiB",
+        help = "Memory used for sorting before spilling to temporary files (e.g. 32MiB)"
+    )]
+    pub sort_memory: u64,
+    #[arg(
+        short = 's',
+        long,
+        help = "Show a summary with counts and total sizes after the listing"
+    )]
+    pub summary: bool,
+    #[arg(
+        long,
+        value_enum,
+        help = "Split the listing into sections (ext, type, owner, day, calendar, size-bucket)"
+    )]
+    pub group_by: Option<GroupBy>,
+    #[arg(
+        long,
+        conflicts_with = "group_by",
+        help = "Group entries by modification day: Today, Yesterday, weekdays, Last week, months"
+    )]
+    pub by_day: bool,
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "max",
+        help = "Show a usage bar relative to the largest entry or the total (max, total)"
+    )]
+    pub size_bar: Option<SizeBar>,
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "all",
+        help = "Color dates by age and sizes by magnitude (age, size, all)"
+    )]
+    pub heatmap: Option<Heatmap>,
+    #[arg(
+        long,
+        value_parser = parse_age_thresholds,
+        default_value = "1h,1d,7d,30d",
+        help = "Four age limits for --heatmap, from newest to oldest"
+    )]
+    pub age_thresholds: Thresholds,
+    #[arg(
+        long,
+        value_parser = parse_size_thresholds,
+        default_value = "1KB,1MB,100MB,1GB",
+        help = "Four size limits for --heatmap, from smallest to largest"
+    )]
+    pub size_thresholds: Thresholds,
+    #[arg(long, help = "Prefix names with Nerd Font icons")]
+    pub icons: bool,
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_value = "never",
+        default_missing_value = "auto",
+        help = "Make names clickable file:// links in supporting terminals (auto, always, never)"
+    )]
+    pub hyperlink: Hyperlink,
+    #[arg(long, help = "Never shorten long names to fit the terminal width")]
+    pub no_truncate: bool,
+    #[arg(
+        long,
+        help = "Wrap long names onto several lines instead of shortening them",
+        conflicts_with = "no_truncate"
+    )]
+    pub wrap_names: bool,
     #[arg(long, help = "Print documentation")]
     pub print_docs: bool,
 }
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Inspect or clean the directory size cache used by --deep (list a
+    /// directory named "cache" as ./cache)
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheAction {
+    /// Show where the cache lives and how many directories it holds
+    Info,
+    /// Drop entries for directories that no longer exist
+    Prune,
+    /// Delete the cache
+    Clear,
+}
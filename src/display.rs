@@ -1,89 +1,710 @@
-use std::io::{self, BufWriter, Write};
+use std::io::{self, BufWriter, StdoutLock, Write};
 
-use std::{fs, path::PathBuf, process::exit};
+use std::{fs, path::Path, process::exit, time::SystemTime};
 
 use owo_colors::OwoColorize;
+use serde::Serialize;
 use tabled::{
     Table,
+    builder::Builder,
     settings::{
-        Color, Style,
+        Color, Style, Width,
         object::{Columns, Rows},
     },
 };
 
-use crate::types::{FileEntry, TableRowNoLine, TableRowWithLine};
+use crate::i18n::{Msg, count, tr};
+use crate::types::{
+    Column, DisplayOptions, FileEntry, FormatOptions, Group, NameFit, SizeMode, Summary, TimeField,
+};
+use crate::utils::{
+    display_width, format_datetime, format_decimal, format_permissions_octal,
+    format_permissions_owner_type, format_permissions_rwx, format_size, hyperlink, rfc3339,
+    terminal_width, truncate_end, truncate_middle, visible_width,
+};
 
-pub fn export_json(
+const MIN_NAME_WIDTH: usize = 12;
+const BAR_WIDTH: usize = 20;
+/// Widest First Match cell, including the line number
+const MATCH_WIDTH: usize = 50;
+
+// Hottest first: last hour, today, this week, this month, older
+const AGE_PALETTE: [Color; 5] = [
+    Color::FG_BRIGHT_RED,
+    Color::FG_BRIGHT_YELLOW,
+    Color::FG_BRIGHT_GREEN,
+    Color::FG_GREEN,
+    Color::FG_BRIGHT_BLACK,
+];
+
+// Smallest first
+const SIZE_PALETTE: [Color; 5] = [
+    Color::FG_BRIGHT_BLACK,
+    Color::FG_BRIGHT_GREEN,
+    Color::FG_BRIGHT_YELLOW,
+    Color::FG_BRIGHT_MAGENTA,
+    Color::FG_BRIGHT_RED,
+];
+
+/// A listing nested next to its summary. Serialized as a struct so that the
+/// fields of every entry keep their order.
+#[derive(Serialize)]
+struct SummedListing<'a, T: ?Sized> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries: Option<&'a T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<&'a T>,
+    summary: &'a Summary,
+}
+
+/// Renders `listing` as pretty JSON. With a summary, the listing is nested
+/// under `entries`, or `groups` when `grouped`, next to a `summary` object
+/// instead of being a bare array.
+pub fn listing_to_json<T: Serialize + ?Sized>(
+    listing: &T,
+    grouped: bool,
+    summary: Option<&Summary>,
+) -> Result<String, serde_json::Error> {
+    match summary {
+        Some(summary) => serde_json::to_string_pretty(&SummedListing {
+            entries: (!grouped).then_some(listing),
+            groups: grouped.then_some(listing),
+            summary,
+        }),
+        None => serde_json::to_string_pretty(listing),
+    }
+}
+
+/// Renders the listing as CSV with the raw values also used in JSON: sizes
+/// in bytes, times in RFC 3339 UTC and the mode as a number. Grouped
+/// listings get a leading `group` column.
+pub fn listing_to_csv(
     files: &[FileEntry],
-    export_path: &PathBuf,
+    groups: Option<&[Group]>,
+    time_fields: &[TimeField],
+    matches: bool,
+) -> String {
+    let mut csv = csv_header(groups.is_some(), time_fields, matches);
+    match groups {
+        Some(groups) => {
+            for group in groups {
+                for file in &group.entries {
+                    csv.push_str(&csv_row(Some(&group.group), file, time_fields, matches));
+                }
+            }
+        }
+        None => {
+            for file in files {
+                csv.push_str(&csv_row(None, file, time_fields, matches));
+            }
+        }
+    }
+    csv
+}
+
+/// `matches` adds the `--contains` columns.
+pub fn csv_header(grouped: bool, time_fields: &[TimeField], matches: bool) -> String {
+    let mut header = vec!["name", "type", "mode", "uid", "gid", "size", "disk_size"];
+    if grouped {
+        header.insert(0, "group");
+    }
+    header.extend(time_fields.iter().map(|field| match field {
+        TimeField::Modified => "modified",
+        TimeField::Accessed => "accessed",
+        TimeField::Changed => "changed",
+        TimeField::Created => "created",
+    }));
+    if matches {
+        header.extend(["matches", "first_match_line", "first_match"]);
+    }
+    header.join(",") + "\n"
+}
+
+pub fn csv_row(
+    group: Option<&str>,
+    file: &FileEntry,
+    time_fields: &[TimeField],
+    matches: bool,
+) -> String {
+    let mut row: Vec<String> = group.map(csv_field).into_iter().collect();
+    row.extend([
+        csv_field(&file.name),
+        file.e_type.to_string(),
+        file.mode.to_string(),
+        file.uid.to_string(),
+        file.gid.to_string(),
+        file.size.to_string(),
+        file.disk_size.to_string(),
+    ]);
+    row.extend(
+        time_fields
+            .iter()
+            .map(|field| file.time(field).map(rfc3339).unwrap_or_default()),
+    );
+    if matches {
+        match &file.content_match {
+            Some(found) => row.extend([
+                found.count.to_string(),
+                found.line.to_string(),
+                csv_field(&found.text),
+            ]),
+            None => row.extend([String::new(), String::new(), String::new()]),
+        }
+    }
+    row.join(",") + "\n"
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn export_json(
+    json_content: &str,
+    export_path: &Path,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let json_content = serde_json::to_string_pretty(files)?;
     fs::write(export_path, json_content)?;
     Ok(())
 }
 
+pub fn print_groups(
+    out: &mut impl Write,
+    groups: &[Group],
+    display_opts: &DisplayOptions,
+) -> io::Result<()> {
+    for (index, group) in groups.iter().enumerate() {
+        if index > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}", group.group.bold().bright_blue())?;
+        print_table_from_files(out, &group.entries, &None, display_opts)?;
+        writeln!(
+            out,
+            "{}",
+            format!(
+                "{} · {}",
+                count(group.count, Msg::Entry, Msg::Entries),
+                format_size(group.total_size, &display_opts.format.size_format).trim_start()
+            )
+            .bright_black()
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_summary(
+    out: &mut impl Write,
+    summary: &Summary,
+    format: &FormatOptions,
+) -> io::Result<()> {
+    let size = |bytes| {
+        format_size(bytes, &format.size_format)
+            .trim_start()
+            .to_string()
+    };
+    writeln!(
+        out,
+        "{}, {}, {} · {} ({} {})",
+        count(summary.files, Msg::File, Msg::Files).bright_cyan(),
+        count(summary.directories, Msg::Directory, Msg::Directories).bright_cyan(),
+        count(summary.symlinks, Msg::Symlink, Msg::Symlinks).bright_cyan(),
+        size(summary.apparent_size).bright_yellow(),
+        size(summary.disk_size).bright_yellow(),
+        tr(Msg::OnDisk),
+    )?;
+
+    if let (Some(newest), Some(oldest)) = (summary.newest_modified, summary.oldest_modified) {
+        writeln!(
+            out,
+            "{}: {} · {}: {}",
+            tr(Msg::Newest),
+            format_datetime(newest, format).bright_green(),
+            tr(Msg::Oldest),
+            format_datetime(oldest, format).bright_green()
+        )?;
+    }
+
+    if summary.dedup_links > 0 {
+        writeln!(
+            out,
+            "{}",
+            count(
+                summary.dedup_links as usize,
+                Msg::HardLinkCountedOnce,
+                Msg::HardLinksCountedOnce
+            )
+            .bright_black()
+        )?;
+    }
+
+    if summary.hidden > 0 {
+        writeln!(
+            out,
+            "{}",
+            format!(
+                "{} {}",
+                count(summary.hidden, Msg::Entry, Msg::Entries),
+                tr(Msg::HiddenByFilters)
+            )
+            .bright_black()
+        )?;
+    }
+    Ok(())
+}
+
+/// One line of the plain name list printed when stdout is not a terminal.
+pub fn name_line(file: &FileEntry, hyperlink_host: Option<&str>) -> String {
+    match hyperlink_host {
+        Some(host) => hyperlink(&file.name, &file.path, host),
+        None => file.name.clone(),
+    }
+}
+
 pub fn print_table_from_files(
+    out: &mut impl Write,
     files: &[FileEntry],
     pattern: &Option<String>,
-    show_line_numbers: bool,
-) {
+    display_opts: &DisplayOptions,
+) -> io::Result<()> {
     if files.is_empty() {
         if pattern.is_some() {
-            println!("{}", "No files found matching the pattern.".red());
+            writeln!(out, "{}", tr(Msg::NoFilesMatching).red())?;
+            out.flush()?;
             exit(1);
         } else {
-            println!("{}", "Directory is empty.".yellow());
+            writeln!(out, "{}", tr(Msg::DirectoryEmpty).yellow())
         }
     } else {
-        if show_line_numbers {
-            let table_rows: Vec<TableRowWithLine> = files
+        let columns = table_columns(display_opts);
+        let mut table = build_table(files, &columns, display_opts, |name| name.to_string());
+
+        if let Some(term_width) = terminal_width() {
+            let total_width = table.total_width();
+            if total_width > term_width {
+                let name_width = files
+                    .iter()
+                    .map(|file| display_width(&file.name))
+                    .max()
+                    .unwrap_or(0);
+                let budget = name_width
+                    .saturating_sub(total_width - term_width)
+                    .max(MIN_NAME_WIDTH);
+
+                match display_opts.name_fit {
+                    NameFit::Truncate => {
+                        table = build_table(files, &columns, display_opts, |name| {
+                            truncate_middle(name, budget)
+                        });
+                    }
+                    NameFit::Wrap => {
+                        let name_col = column_index(&columns, Column::Name);
+                        table.modify(Columns::one(name_col), Width::wrap(budget));
+                    }
+                    NameFit::Full => {}
+                }
+            }
+        }
+
+        apply_cell_colors(&mut table, files, &columns, display_opts);
+        print_styled_table(out, table, &columns)
+    }
+}
+
+/// Draws the table of `print_table_from_files` one row at a time, for
+/// listings too large to lay out at once. Column widths are measured on the
+/// first rows: later cells that are wider push the rest of their row out of
+/// line, except names, which are shortened to fit their column (also with
+/// `--wrap-names`).
+pub struct StreamedTable<'a> {
+    display_opts: &'a DisplayOptions,
+    columns: Vec<Column>,
+    widths: Vec<usize>,
+    name_limit: Option<usize>,
+    rows: usize,
+    now: SystemTime,
+    out: BufWriter<StdoutLock<'static>>,
+}
+
+impl<'a> StreamedTable<'a> {
+    /// Prints the header and the `first` rows. `total` is the number of
+    /// rows to come, if known, and sizes the line number column; without
+    /// it there is room for seven digits.
+    pub fn start(
+        first: &[FileEntry],
+        total: Option<usize>,
+        display_opts: &'a DisplayOptions,
+    ) -> io::Result<StreamedTable<'a>> {
+        let columns = table_columns(display_opts);
+        let measure = |fit_name: &dyn Fn(&str) -> String| -> Vec<usize> {
+            columns
                 .iter()
-                .map(|file| TableRowWithLine {
-                    line_number: file.line_number.clone(),
-                    name: file.name.clone(),
-                    e_type: file.e_type.to_string(),
-                    permissions: file.permissions.clone(),
-                    size: file.size.clone(),
-                    modified: file.modified.clone(),
+                .map(|column| {
+                    let cells = first.iter().map(|file| match column {
+                        Column::LineNumber => 0,
+                        _ => visible_width(&cell_text(file, column, display_opts, fit_name)),
+                    });
+                    cells.max().unwrap_or(0).max(display_width(column.header()))
                 })
-                .collect();
-            print_styled_table(Table::new(&table_rows), true);
-        } else {
-            let table_rows: Vec<TableRowNoLine> = files
+                .collect()
+        };
+
+        let mut widths = measure(&|name| name.to_string());
+        if display_opts.show_line_numbers {
+            let last = total.unwrap_or(9_999_999);
+            widths[0] = widths[0].max(last.to_string().len());
+        }
+        let name_col = column_index(&columns, Column::Name);
+        let name_width = first
+            .iter()
+            .map(|file| display_width(&file.name))
+            .max()
+            .unwrap_or(0);
+        let mut name_limit = name_width;
+
+        if let Some(term_width) = terminal_width() {
+            // Every cell is padded by one space and followed by a border
+            let total_width = widths.iter().map(|width| width + 3).sum::<usize>() + 1;
+            if total_width > term_width && display_opts.name_fit != NameFit::Full {
+                name_limit = name_width
+                    .saturating_sub(total_width - term_width)
+                    .max(MIN_NAME_WIDTH);
+                widths[name_col] = measure(&|name| truncate_middle(name, name_limit))[name_col];
+            }
+        }
+
+        let mut table = StreamedTable {
+            display_opts,
+            name_limit: (display_opts.name_fit != NameFit::Full).then_some(name_limit),
+            columns,
+            widths,
+            rows: 0,
+            now: SystemTime::now(),
+            out: BufWriter::new(io::stdout().lock()),
+        };
+        table.border('╭', '┬', '╮')?;
+        let header = table
+            .columns
+            .iter()
+            .map(|column| Color::FG_BRIGHT_GREEN.colorize(column.header()))
+            .collect::<Vec<_>>();
+        let header_widths = table
+            .columns
+            .iter()
+            .map(|column| display_width(column.header()))
+            .collect::<Vec<_>>();
+        table.line(&header, &header_widths)?;
+        table.border('├', '┼', '┤')?;
+        for file in first {
+            table.row(file)?;
+        }
+        Ok(table)
+    }
+
+    pub fn row(&mut self, file: &FileEntry) -> io::Result<()> {
+        self.rows += 1;
+        let fit_name = |name: &str| match self.name_limit {
+            Some(limit) => truncate_middle(name, limit),
+            None => name.to_string(),
+        };
+
+        let mut cells = Vec::with_capacity(self.columns.len());
+        let mut widths = Vec::with_capacity(self.columns.len());
+        for column in &self.columns {
+            let text = match column {
+                Column::LineNumber => self.rows.to_string(),
+                _ => cell_text(file, column, self.display_opts, fit_name),
+            };
+            let color = cell_color(file, column, self.display_opts, self.now)
+                .unwrap_or_else(|| column_color(column));
+            widths.push(visible_width(&text));
+            cells.push(color.colorize(text));
+        }
+        self.line(&cells, &widths)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.border('╰', '┴', '╯')?;
+        self.out.flush()
+    }
+
+    /// Writes one line of already colored `cells`, whose visible widths
+    /// are `cell_widths`, padded to the column widths.
+    fn line(&mut self, cells: &[String], cell_widths: &[usize]) -> io::Result<()> {
+        let mut line = String::from("│");
+        for ((cell, cell_width), width) in cells.iter().zip(cell_widths).zip(&self.widths) {
+            line.push(' ');
+            line.push_str(cell);
+            line.push_str(&" ".repeat(width.saturating_sub(*cell_width) + 1));
+            line.push('│');
+        }
+        writeln!(self.out, "{}", line)
+    }
+
+    fn border(&mut self, left: char, middle: char, right: char) -> io::Result<()> {
+        let segments = self
+            .widths
+            .iter()
+            .map(|width| "─".repeat(width + 2))
+            .collect::<Vec<_>>();
+        writeln!(
+            self.out,
+            "{}{}{}",
+            left,
+            segments.join(&middle.to_string()),
+            right
+        )
+    }
+}
+
+/// Colors individual time and Size cells by how old or large the entry is,
+/// overriding the plain column colors.
+fn apply_cell_colors(
+    table: &mut Table,
+    files: &[FileEntry],
+    columns: &[Column],
+    display_opts: &DisplayOptions,
+) {
+    let now = SystemTime::now();
+
+    for (index, file) in files.iter().enumerate() {
+        for (col, column) in columns.iter().enumerate() {
+            if let Some(color) = cell_color(file, column, display_opts, now) {
+                table.modify((index + 1, col), color);
+            }
+        }
+    }
+}
+
+/// Heatmap color of one cell, if it has one. Times in the future are always
+/// highlighted since they usually point at a wrong clock.
+fn cell_color(
+    file: &FileEntry,
+    column: &Column,
+    display_opts: &DisplayOptions,
+    now: SystemTime,
+) -> Option<Color> {
+    match column {
+        Column::Time(field) => {
+            let time = file.time(field)?;
+            if time > now {
+                Some(Color::BG_RED | Color::FG_BRIGHT_WHITE)
+            } else {
+                let limits = display_opts.age_heatmap.as_ref()?;
+                let age = now
+                    .duration_since(time)
+                    .map(|age| age.as_secs())
+                    .unwrap_or(0);
+                Some(heat_color(age, limits, &AGE_PALETTE))
+            }
+        }
+        Column::Size => {
+            let limits = display_opts.size_heatmap.as_ref()?;
+            Some(heat_color(
+                file.listed_size(&display_opts.size_mode),
+                limits,
+                &SIZE_PALETTE,
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn heat_color(value: u64, limits: &[u64], palette: &[Color]) -> Color {
+    let bucket = limits
+        .iter()
+        .position(|limit| value < *limit)
+        .unwrap_or(limits.len());
+    palette[bucket.min(palette.len() - 1)].clone()
+}
+
+fn table_columns(display_opts: &DisplayOptions) -> Vec<Column> {
+    let mut columns = Vec::new();
+    if display_opts.show_line_numbers {
+        columns.push(Column::LineNumber);
+    }
+    columns.extend([
+        Column::Name,
+        Column::Type,
+        Column::Permissions,
+        Column::Size,
+    ]);
+    if display_opts.size_mode == SizeMode::Both {
+        columns.push(Column::DiskSize);
+    }
+    if display_opts.size_bar.is_some() {
+        columns.push(Column::Usage);
+    }
+    columns.extend(
+        display_opts
+            .time_fields
+            .iter()
+            .map(|field| Column::Time(*field)),
+    );
+    if display_opts.show_matches {
+        columns.extend([Column::Matches, Column::FirstMatch]);
+    }
+    columns
+}
+
+fn column_index(columns: &[Column], column: Column) -> usize {
+    columns.iter().position(|c| *c == column).unwrap_or(0)
+}
+
+fn build_table(
+    files: &[FileEntry],
+    columns: &[Column],
+    display_opts: &DisplayOptions,
+    fit_name: impl Fn(&str) -> String,
+) -> Table {
+    let mut builder = Builder::default();
+    builder.push_record(columns.iter().map(|column| column.header()));
+
+    for file in files {
+        builder.push_record(
+            columns
                 .iter()
-                .map(|file| TableRowNoLine {
-                    name: file.name.clone(),
-                    e_type: file.e_type.to_string(),
-                    permissions: file.permissions.clone(),
-                    size: file.size.clone(),
-                    modified: file.modified.clone(),
-                })
-                .collect();
-            print_styled_table(Table::new(&table_rows), false);
+                .map(|column| cell_text(file, column, display_opts, &fit_name)),
+        );
+    }
+
+    builder.build()
+}
+
+fn cell_text(
+    file: &FileEntry,
+    column: &Column,
+    display_opts: &DisplayOptions,
+    fit_name: impl Fn(&str) -> String,
+) -> String {
+    let format = &display_opts.format;
+    match column {
+        Column::LineNumber => file.line_number.clone(),
+        Column::Name => {
+            let mut name = fit_name(&file.name);
+            if let Some(host) = &display_opts.hyperlink_host {
+                name = hyperlink(&name, &file.path, host);
+            }
+            match &display_opts.icons {
+                Some(theme) => format!("{} {}", theme.icon_for(file), name),
+                None => name,
+            }
+        }
+        Column::Type => file.e_type.label().to_string(),
+        Column::Permissions => {
+            if format.octal_perms {
+                format_permissions_octal(file.mode)
+            } else if format.owner_perms {
+                format_permissions_owner_type(file.uid)
+            } else {
+                format_permissions_rwx(file.mode)
+            }
+        }
+        Column::Size => {
+            // With both sizes shown this column is the apparent one
+            let size = match display_opts.size_mode {
+                SizeMode::Both => file.size,
+                _ => file.listed_size(&display_opts.size_mode),
+            };
+            with_size_notes(
+                &format_size(size, &format.size_format),
+                file,
+                display_opts.size_mode != SizeMode::Both,
+            )
         }
+        Column::DiskSize => with_size_notes(
+            &format_size(file.disk_size, &format.size_format),
+            file,
+            true,
+        ),
+        Column::Usage => size_bar(
+            file.listed_size(&display_opts.size_mode),
+            display_opts.size_bar.unwrap_or(0),
+        ),
+        Column::Time(field) => match file.time(field) {
+            Some(time) => format_datetime(time, format),
+            // Birth time on filesystems that do not record it
+            None => tr(Msg::Unknown).to_string(),
+        },
+        Column::Matches => file
+            .content_match
+            .as_ref()
+            .map(|found| found.count.to_string())
+            .unwrap_or_default(),
+        Column::FirstMatch => file
+            .content_match
+            .as_ref()
+            .map(|found| truncate_end(&format!("{}: {}", found.line, found.text), MATCH_WIDTH))
+            .unwrap_or_default(),
     }
 }
 
-fn print_styled_table(mut table: Table, has_line_numbers: bool) {
+/// Appends "(incomplete)" to partial sizes and, on the column showing the
+/// allocated size, "(sparse)" to sparse files.
+fn with_size_notes(size: &str, file: &FileEntry, shows_allocation: bool) -> String {
+    let mut cell = size.to_string();
+    if file.incomplete {
+        cell.push_str(&format!(" ({})", tr(Msg::Incomplete)));
+    }
+    if file.sparse && shows_allocation {
+        cell.push_str(&format!(" ({})", tr(Msg::Sparse)));
+    }
+    cell
+}
+
+/// Draws `size` as a bar of `BAR_WIDTH` cells relative to `reference`,
+/// using eighth-block characters for the partial cell.
+fn size_bar(size: u64, reference: u64) -> String {
+    const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    let ratio = if reference == 0 {
+        0.0
+    } else {
+        (size as f64 / reference as f64).min(1.0)
+    };
+    let eighths = (ratio * (BAR_WIDTH * 8) as f64).round() as usize;
+
+    let mut bar = "█".repeat(eighths / 8);
+    let remainder = eighths % 8;
+    if remainder > 0 {
+        bar.push(PARTIAL[remainder]);
+    }
+    let padding = BAR_WIDTH - bar.chars().count();
+
+    format!(
+        "{}{} {:>5}%",
+        bar,
+        " ".repeat(padding),
+        format_decimal(ratio * 100.0, 1)
+    )
+}
+
+fn print_styled_table(
+    out: &mut impl Write,
+    mut table: Table,
+    columns: &[Column],
+) -> io::Result<()> {
     table.with(Style::rounded());
-    let mut col_index = 0;
-    if has_line_numbers {
-        table.modify(Columns::one(col_index), Color::FG_BRIGHT_WHITE);
-        col_index += 1;
-    }
-    table.modify(Columns::one(col_index), Color::FG_BRIGHT_CYAN); // Name
-    col_index += 1;
-    table.modify(Columns::one(col_index), Color::FG_BRIGHT_MAGENTA); // Type
-    col_index += 1;
-    table.modify(Columns::one(col_index), Color::FG_BRIGHT_MAGENTA); // Permissions
-    col_index += 1;
-    table.modify(Columns::one(col_index), Color::FG_BRIGHT_YELLOW); // Size
-    col_index += 1;
-    table.modify(Columns::one(col_index), Color::FG_BRIGHT_GREEN); // Modified Date
+    for (col_index, column) in columns.iter().enumerate() {
+        table.modify(Columns::one(col_index), column_color(column));
+    }
 
     table.modify(Rows::first(), Color::FG_BRIGHT_GREEN);
-    let stdout = io::stdout();
-    let mut w = BufWriter::new(stdout.lock());
-    writeln!(w, "{}", table).unwrap();
-    w.flush().unwrap();
+    writeln!(out, "{}", table)
+}
+
+fn column_color(column: &Column) -> Color {
+    match column {
+        Column::LineNumber => Color::FG_BRIGHT_WHITE,
+        Column::Name => Color::FG_BRIGHT_CYAN,
+        Column::Type | Column::Permissions => Color::FG_BRIGHT_MAGENTA,
+        Column::Size | Column::DiskSize => Color::FG_BRIGHT_YELLOW,
+        Column::Usage => Color::FG_BRIGHT_BLUE,
+        Column::Time(_) => Color::FG_BRIGHT_GREEN,
+        Column::Matches => Color::FG_BRIGHT_RED,
+        Column::FirstMatch => Color::FG_WHITE,
+    }
 }
@@ -1,197 +1,269 @@
-use std::{env, fs, path::PathBuf};
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsString,
+    fs, io,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
 
-use chrono_tz::Tz;
 use rayon::prelude::*;
 
-use crate::types::{EntryType, FileEntry, TimeFormat};
-use crate::utils::{
-    format_datetime, format_permissions_octal, format_permissions_owner_type,
-    format_permissions_rwx, format_size,
+use crate::cache::SizeCache;
+use crate::i18n::{Msg, tr};
+use crate::progress::Progress;
+use crate::search::IgnoreRules;
+use crate::stat::{DirHandle, Fields, Stat, stat_path};
+use crate::types::{
+    CacheMode, EntrySize, EntryType, FileEntry, ScanOptions, ScanResult, SharedLinks, SizeFormat,
+    TimeField,
 };
 
+const SPARSE_MIN_HOLE: u64 = 1024 * 1024;
+
+/// Directory entries read and stat'ed together before being emitted
+const SCAN_BATCH: usize = 4096;
+
+/// Lists `path` and any `--include` directories, handing each entry to
+/// `emit` in directory order. Entries are read and stat'ed `SCAN_BATCH` at
+/// a time, so a huge directory is never held in memory by the scan itself.
+/// The scan ends early once `emit` breaks. `size_format` is only used by
+/// the `--deep` progress line.
 pub fn get_file(
-    path: &PathBuf,
-    pattern: &Option<String>,
-    include_dirs: &Option<Vec<String>>,
-    exclude_dirs: &Option<Vec<String>>,
-    show_line_numbers: bool,
-    octal_perms: bool,
-    owner_type: bool,
-    time_format: &TimeFormat,
-    timezone: &Tz,
-    custom_format: &str,
-    deep: bool,
-    toggle_clock: bool,
-    show_current_dir: bool,
-) -> Vec<FileEntry> {
-    let mut all_entries = Vec::new();
+    path: &Path,
+    opts: &ScanOptions,
+    size_format: &SizeFormat,
+    emit: &mut dyn FnMut(FileEntry) -> ControlFlow<()>,
+) -> ScanResult {
+    let mut hidden = 0;
     let mut directories_to_scan = Vec::new();
-    directories_to_scan.push(path.clone());
+    directories_to_scan.push(path.to_path_buf());
 
-    if let Some(include_list) = include_dirs {
+    if let Some(include_list) = &opts.include {
         for dir_str in include_list {
             let dir_path = PathBuf::from(dir_str);
             if dir_path.exists() && dir_path.is_dir() {
                 directories_to_scan.push(dir_path);
             } else {
-                eprintln!(
-                    "Warning: Directory '{}' does not exist or is not a directory",
-                    dir_str
-                );
+                eprintln!("{}", tr(Msg::MissingIncludeDir).replace("{}", dir_str));
             }
         }
     }
 
-    for dir_path in directories_to_scan {
-        let mut dir_entries = scan_single_directory(
-            &dir_path,
-            pattern,
-            exclude_dirs,
-            show_line_numbers,
-            octal_perms,
-            owner_type,
-            time_format,
-            timezone,
-            custom_format,
-            deep,
-            toggle_clock,
-            show_current_dir,
-        );
+    let scanning_multiple = opts.include.as_ref().is_some_and(|list| !list.is_empty());
+    let walk_state = WalkState {
+        progress: Progress::new(opts.timeout),
+        linked: Mutex::default(),
+    };
+    // Symlinked directories make the listing depend on the walk, so the
+    // cache is only used for plain walks
+    let use_cache = opts.deep && opts.size_cache != CacheMode::Off && !opts.follow_symlinks;
+    let done = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let reporter = opts
+            .deep
+            .then(|| scope.spawn(|| walk_state.progress.report_until(&done, size_format)));
 
-        let scanning_multiple = include_dirs.is_some() && include_dirs.as_ref().unwrap().len() > 0;
+        for dir_path in directories_to_scan {
+            let root_stat = stat_path(&dir_path, true).ok();
+            let cache = root_stat.filter(|_| use_cache).map(|stat| {
+                SizeCache::load(
+                    &dir_path,
+                    &stat,
+                    opts.size_cache == CacheMode::Refresh,
+                    opts.cache_max_age,
+                )
+            });
+            let walk = DeepWalk {
+                state: &walk_state,
+                claims_links: true,
+                root_dev: root_stat.map_or(0, |stat| stat.dev),
+                cache: cache.as_ref(),
+            };
+            let dir_str = dir_path.to_string_lossy();
+            let clean_dir = dir_str.trim_end_matches('/').trim_end_matches('\\');
 
-        if scanning_multiple {
-            for entry in &mut dir_entries {
-                let dir_str = dir_path.to_string_lossy();
-                let clean_dir = dir_str.trim_end_matches('/').trim_end_matches('\\');
-                entry.name = format!("{}/{}", clean_dir, entry.name);
+            let mut emitted = ControlFlow::Continue(());
+            hidden += scan_single_directory(&dir_path, opts, &walk, &mut |mut entry| {
+                if scanning_multiple {
+                    entry.name = format!("{}/{}", clean_dir, entry.name);
+                }
+                emitted = emit(entry);
+                emitted
+            });
+            if let Some(cache) = cache {
+                cache.save();
             }
+            if emitted.is_break() {
+                break;
+            }
+        }
+        done.store(true, Ordering::Relaxed);
+        if let Some(reporter) = reporter {
+            reporter.thread().unpark();
         }
+    });
 
-        all_entries.extend(dir_entries);
+    ScanResult {
+        hidden,
+        stopped: walk_state.progress.stop_reason(),
     }
-
-    all_entries
 }
 
+/// Emits the entries of one directory, until `emit` breaks, and returns how
+/// many were hidden by the filters.
 fn scan_single_directory(
-    path: &PathBuf,
-    pattern: &Option<String>,
-    exclude_patterns: &Option<Vec<String>>,
-    show_line_numbers: bool,
-    octal_perms: bool,
-    owner_type: bool,
-    time_format: &TimeFormat,
-    timezone: &Tz,
-    custom_format: &str,
-    deep: bool,
-    toggle_clock: bool,
-    show_current_dir: bool,
-) -> Vec<FileEntry> {
-    let mut data = Vec::new();
+    path: &Path,
+    opts: &ScanOptions,
+    walk: &DeepWalk,
+    emit: &mut dyn FnMut(FileEntry) -> ControlFlow<()>,
+) -> usize {
     let mut line_number = 1;
+    let mut hidden = 0;
 
-    // Add current directory entry if requested
-    if show_current_dir {
-        if let Ok(current_dir) = env::current_dir() {
-            let current_dir_entry = create_current_dir_entry(
-                &current_dir,
-                if show_line_numbers {
-                    line_number.to_string()
-                } else {
-                    String::new()
-                },
-                octal_perms,
-                owner_type,
-                time_format,
-                timezone,
-                custom_format,
-                deep,
-                toggle_clock,
-            );
-            if let Some(entry) = current_dir_entry {
-                data.push(entry);
-                line_number += 1;
-            }
+    // Add current directory entry if requested; a content search only
+    // lists files
+    if opts.show_cwd
+        && opts.search.is_none()
+        && let Ok(current_dir) = env::current_dir()
+        && let Some(entry) = create_current_dir_entry(
+            &current_dir,
+            if opts.show_line_numbers {
+                line_number.to_string()
+            } else {
+                String::new()
+            },
+            opts,
+            walk,
+        )
+    {
+        if emit(entry).is_break() {
+            return hidden;
         }
+        line_number += 1;
     }
 
-    if let Ok(read_dir) = fs::read_dir(path) {
-        for entry in read_dir {
-            if let Ok(file) = entry {
-                if should_include_file(&file, pattern)
-                    && !should_exclude_file(&file, exclude_patterns)
-                {
-                    map_data(
-                        file,
-                        &mut data,
-                        if show_line_numbers {
-                            line_number.to_string()
-                        } else {
-                            String::new()
-                        },
-                        octal_perms,
-                        owner_type,
-                        time_format,
-                        timezone,
-                        custom_format,
-                        deep,
-                        toggle_clock,
-                    );
-                    line_number += 1;
+    // Filtering only needs the name; the stat calls, deep sizes and content
+    // searches then run on the thread pool, and collecting keeps the
+    // directory order.
+    let dir = DirHandle::open(path);
+    let ignore = opts
+        .search
+        .as_ref()
+        .filter(|search| search.ignore_files)
+        .map(|_| IgnoreRules::load(path));
+    let Ok(read_dir) = fs::read_dir(path) else {
+        return hidden;
+    };
+    let mut included = Vec::new();
+    let mut files = read_dir.flatten().peekable();
+    while files.peek().is_some() {
+        for file in files.by_ref() {
+            let is_dir = file.file_type().is_ok_and(|t| t.is_dir());
+            let ignored = ignore
+                .as_ref()
+                .is_some_and(|rules| rules.is_ignored(&file.file_name(), is_dir));
+            // The search would drop directories after stat'ing them
+            let searched_dir = is_dir && opts.search.is_some();
+            if should_include_file(&file, &opts.pattern)
+                && !should_exclude_file(&file, &opts.exclude)
+                && !ignored
+                && !searched_dir
+            {
+                included.push((file, line_number));
+                line_number += 1;
+                if included.len() == SCAN_BATCH {
+                    break;
                 }
+            } else {
+                hidden += 1;
+            }
+        }
+
+        let mut entries = map_batch(std::mem::take(&mut included), &dir, opts, walk);
+        if let Some(search) = &opts.search {
+            let listed = entries.len();
+            entries = search.filter(entries);
+            hidden += listed - entries.len();
+        }
+        for entry in entries {
+            if emit(entry).is_break() {
+                return hidden;
             }
         }
     }
-    data
+    hidden
+}
+
+fn map_batch(
+    included: Vec<(fs::DirEntry, usize)>,
+    dir: &DirHandle,
+    opts: &ScanOptions,
+    walk: &DeepWalk,
+) -> Vec<FileEntry> {
+    // Symlinks are followed so that links to directories show as such
+    let names: Vec<_> = included
+        .iter()
+        .map(|(file, _)| {
+            let is_symlink = file.file_type().is_ok_and(|t| t.is_symlink());
+            (file.file_name(), is_symlink)
+        })
+        .collect();
+    let requests: Vec<_> = names
+        .iter()
+        .map(|(name, follow)| (name.as_os_str(), *follow))
+        .collect();
+    let stats = dir.stat_many(&requests, opts.stat_fields);
+
+    included
+        .into_par_iter()
+        .zip(stats)
+        .filter_map(|((file, line_number), stat)| {
+            map_data(
+                file,
+                stat,
+                dir,
+                if opts.show_line_numbers {
+                    line_number.to_string()
+                } else {
+                    String::new()
+                },
+                opts,
+                walk,
+            )
+        })
+        .collect()
 }
 
 fn create_current_dir_entry(
-    current_dir: &PathBuf,
+    current_dir: &Path,
     line_number: String,
-    octal_perms: bool,
-    owner_type: bool,
-    time_format: &TimeFormat,
-    timezone: &Tz,
-    custom_format: &str,
-    deep: bool,
-    toggle_clock: bool,
+    opts: &ScanOptions,
+    walk: &DeepWalk,
 ) -> Option<FileEntry> {
-    if let Ok(meta) = fs::metadata(current_dir) {
-        let file_size = if deep {
-            get_dir_size(current_dir)
-        } else {
-            meta.len()
-        };
-
-        let raw_modified = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
-        let modified_date = format_datetime(
-            raw_modified,
-            time_format,
-            timezone,
-            custom_format,
-            toggle_clock,
-        );
-
-        Some(FileEntry {
-            line_number,
-            name: format!("./"),
-            e_type: EntryType::Dir,
-            permissions: if octal_perms {
-                format_permissions_octal(&meta)
-            } else if owner_type {
-                format_permissions_owner_type(&meta)
-            } else {
-                format_permissions_rwx(&meta)
-            },
-            size: format_size(file_size),
-            modified: modified_date,
-            raw_size: file_size,
-            raw_modified,
-        })
-    } else {
-        None
-    }
+    let stat = stat_path(current_dir, true).ok()?;
+    // The row already contains every other entry, so it must not take
+    // their hard links from them
+    let walk = DeepWalk {
+        root_dev: stat.dev,
+        claims_links: false,
+        ..*walk
+    };
+    Some(build_entry(
+        line_number,
+        "./".to_string(),
+        EntryType::Dir,
+        current_dir,
+        &stat,
+        opts,
+        &walk,
+    ))
 }
 
 fn should_exclude_file(file: &fs::DirEntry, exclude_patterns: &Option<Vec<String>>) -> bool {
@@ -208,65 +280,123 @@ fn should_exclude_file(file: &fs::DirEntry, exclude_patterns: &Option<Vec<String
     false
 }
 
+/// Builds the entry from its stat, taken relative to its directory. The
+/// type comes from the directory listing itself, so only symlinks were
+/// followed.
 fn map_data(
     file: fs::DirEntry,
-    data: &mut Vec<FileEntry>,
+    stat: io::Result<Stat>,
+    dir: &DirHandle,
     line_number: String,
-    octal_perms: bool,
-    owner_type: bool,
-    time_format: &TimeFormat,
-    timezone: &Tz,
-    custom_format: &str,
-    deep: bool,
-    toggle_clock: bool,
-) {
-    if let Ok(meta) = fs::metadata(file.path()) {
-        let file_size = if meta.is_dir() && deep {
-            get_dir_size(&file.path())
-        } else {
-            meta.len()
-        };
+    opts: &ScanOptions,
+    walk: &DeepWalk,
+) -> Option<FileEntry> {
+    let name = file.file_name();
+    let is_symlink = file.file_type().is_ok_and(|t| t.is_symlink());
+    let stat = match stat {
+        // Fall back to the link itself so broken symlinks are still listed
+        Err(_) if is_symlink => dir.stat(&name, false, opts.stat_fields),
+        stat => stat,
+    }
+    .ok()?;
 
-        let raw_modified = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
-        let modified_date = format_datetime(
-            raw_modified,
-            time_format,
-            timezone,
-            custom_format,
-            toggle_clock,
-        );
+    let mut filename = name
+        .into_string()
+        .unwrap_or_else(|_| "Unknown name.".to_string());
+    if stat.is_dir() {
+        filename.push('/');
+    }
 
-        let mut filename = file
-            .file_name()
-            .into_string()
-            .unwrap_or_else(|_| "Unknown name.".to_string());
-        if meta.is_dir() {
-            filename.push('/');
-        }
+    let e_type = if is_symlink {
+        EntryType::Symlink
+    } else if stat.is_dir() {
+        EntryType::Dir
+    } else {
+        EntryType::File
+    };
 
-        data.push(FileEntry {
-            line_number,
-            name: filename,
-            e_type: if meta.is_dir() {
-                EntryType::Dir
-            } else {
-                EntryType::File
-            },
-            permissions: if octal_perms {
-                format_permissions_octal(&meta)
-            } else if owner_type {
-                format_permissions_owner_type(&meta)
+    Some(build_entry(
+        line_number,
+        filename,
+        e_type,
+        &file.path(),
+        &stat,
+        opts,
+        walk,
+    ))
+}
+
+fn build_entry(
+    line_number: String,
+    name: String,
+    e_type: EntryType,
+    path: &Path,
+    stat: &Stat,
+    opts: &ScanOptions,
+    walk: &DeepWalk,
+) -> FileEntry {
+    // Symlinked directories and other filesystems are only walked on request.
+    // A content search drops directories anyway.
+    let walk_dir = stat.is_dir()
+        && opts.deep
+        && opts.search.is_none()
+        && (opts.follow_symlinks || !matches!(e_type, EntryType::Symlink))
+        && (!opts.one_file_system || stat.dev == walk.root_dev);
+    let file_size = if walk_dir {
+        get_dir_size(path, stat, opts, walk, &Seen::default()).0
+    } else {
+        let linked = stat.is_file() && stat.nlink > 1 && opts.deep;
+        EntrySize {
+            apparent: stat.size,
+            allocated: stat.allocated,
+            shared: if linked {
+                walk.claim(
+                    &LinkedFile {
+                        dev: stat.dev,
+                        ino: stat.ino,
+                        apparent: stat.size,
+                        allocated: stat.allocated,
+                    },
+                    opts,
+                )
             } else {
-                format_permissions_rwx(&meta)
+                SharedLinks::default()
             },
-            size: format_size(file_size),
-            modified: modified_date,
-            raw_size: file_size,
-            raw_modified,
-        });
+            ..EntrySize::default()
+        }
+    };
+
+    let wants = |field: TimeField| opts.time_fields.contains(&field);
+
+    FileEntry {
+        line_number,
+        name,
+        e_type,
+        path: path.to_path_buf(),
+        mode: stat.mode,
+        uid: stat.uid,
+        gid: stat.gid,
+        size: file_size.apparent,
+        disk_size: file_size.allocated,
+        dedup_links: file_size.dedup_links,
+        shared: file_size.shared,
+        sparse: stat.is_file() && is_sparse(file_size),
+        incomplete: file_size.incomplete,
+        modified: stat.modified,
+        accessed: wants(TimeField::Accessed).then_some(stat.accessed),
+        changed: wants(TimeField::Changed).then_some(stat.changed),
+        created: stat.created.filter(|_| wants(TimeField::Created)),
+        content_match: None,
     }
 }
 
+/// A file counts as sparse when less than half of its length is allocated
+/// and the holes add up to at least `SPARSE_MIN_HOLE`, so that small files
+/// stored inline by the filesystem are not flagged.
+fn is_sparse(size: EntrySize) -> bool {
+    size.allocated < size.apparent / 2 && size.apparent - size.allocated >= SPARSE_MIN_HOLE
+}
+
 fn should_include_file(file: &fs::DirEntry, pattern: &Option<String>) -> bool {
     let filename = file.file_name().to_string_lossy().to_lowercase();
 
@@ -277,25 +407,329 @@ fn should_include_file(file: &fs::DirEntry, pattern: &Option<String>) -> bool {
     filename.contains(&search_pattern.to_lowercase())
 }
 
-pub fn get_dir_size(path: &PathBuf) -> u64 {
-    if let Ok(entries) = fs::read_dir(path) {
-        entries
-            .filter_map(Result::ok)
-            .par_bridge()
-            .map(|entry| {
-                let meta = entry.metadata();
-                if let Ok(m) = meta {
-                    if m.is_dir() {
-                        get_dir_size(&entry.path())
-                    } else {
-                        m.len()
-                    }
-                } else {
-                    0
-                }
-            })
-            .sum()
-    } else {
-        0
+/// State shared by every `--deep` size computation of one listing.
+struct WalkState {
+    progress: Progress,
+    /// Hard-linked files counted by any listed entry so far
+    linked: Mutex<HashSet<(u64, u64)>>,
+}
+
+/// What the walk of one listed entry has counted, so that a hard-linked file
+/// is counted once in the entry's size even when its links sit in different
+/// directories, and no directory is walked twice. Each entry gets its own,
+/// so that every entry shows its whole size; `WalkState::linked` then keeps
+/// files shared between entries out of the summary.
+#[derive(Default)]
+struct Seen {
+    inodes: Mutex<HashSet<(u64, u64)>>,
+    dirs: Mutex<HashSet<(u64, u64)>>,
+}
+
+#[derive(Clone, Copy)]
+struct DeepWalk<'a> {
+    state: &'a WalkState,
+    /// Whether hard links counted here are recorded for the whole listing
+    claims_links: bool,
+    /// Device of the listed directory, for `--one-file-system`
+    root_dev: u64,
+    /// Size cache of the listed directory, unless `--no-cache`
+    cache: Option<&'a SizeCache>,
+}
+
+impl DeepWalk<'_> {
+    /// Records a hard-linked file counted by the current entry, returning it
+    /// as shared when another entry already counted it.
+    fn claim(&self, file: &LinkedFile, opts: &ScanOptions) -> SharedLinks {
+        if opts.count_links || !self.claims_links {
+            return SharedLinks::default();
+        }
+        let mut linked = self.state.linked.lock().unwrap();
+        if linked.insert((file.dev, file.ino)) {
+            SharedLinks::default()
+        } else {
+            SharedLinks {
+                links: 1,
+                apparent: file.apparent,
+                allocated: file.allocated,
+            }
+        }
+    }
+}
+
+/// Adds up everything below `path`. Unless `count_links` is set, a file with
+/// several hard links is counted at the first link the walk reaches.
+/// Directories already seen during this entry's walk, e.g. through a bind
+/// mount or a followed symlink loop, add nothing. After Ctrl-C or
+/// `--timeout` the size is returned as counted so far and marked incomplete.
+///
+/// Also returns whether the size only depends on the directory itself and
+/// can go into the size cache: it must be complete, hold no hard-linked
+/// files (their count depends on where else they were seen) and stay on
+/// one filesystem (so that `--one-file-system` does not change it).
+fn get_dir_size(
+    path: &Path,
+    stat: &Stat,
+    opts: &ScanOptions,
+    walk: &DeepWalk,
+    seen: &Seen,
+) -> (EntrySize, bool) {
+    let progress = &walk.state.progress;
+    if progress.should_stop() {
+        let size = EntrySize {
+            incomplete: true,
+            ..EntrySize::default()
+        };
+        return (size, false);
+    }
+    progress.enter_dir(path);
+
+    if !seen.dirs.lock().unwrap().insert(stat.inode()) {
+        return (EntrySize::default(), false);
+    }
+    if let Some(size) = walk.cache.and_then(|cache| cache.get(stat)) {
+        progress.add_bytes(size.apparent);
+        return (size, true);
+    }
+
+    let dir = DirHandle::open(path);
+    let (listing, complete) = read_listing(&dir, opts);
+
+    let mut size = EntrySize {
+        apparent: listing.apparent,
+        allocated: listing.allocated,
+        ..EntrySize::default()
+    };
+    for file in &listing.linked {
+        if opts.count_links || seen.inodes.lock().unwrap().insert((file.dev, file.ino)) {
+            size.apparent += file.apparent;
+            size.allocated += file.allocated;
+            size.shared = size.shared + walk.claim(file, opts);
+        } else {
+            size.dedup_links += 1;
+        }
+    }
+
+    progress.add_bytes(size.apparent);
+
+    let requests: Vec<_> = listing
+        .subdirs
+        .iter()
+        .map(|name| (name.as_os_str(), opts.follow_symlinks))
+        .collect();
+    let stats = dir.stat_many(&requests, Fields::default());
+
+    let (below, below_cacheable) = listing
+        .subdirs
+        .par_iter()
+        .zip(stats)
+        .map(|(name, sub_stat)| match sub_stat {
+            Ok(sub_stat) if !opts.one_file_system || sub_stat.dev == walk.root_dev => {
+                let (size, cacheable) = get_dir_size(&path.join(name), &sub_stat, opts, walk, seen);
+                (size, cacheable && sub_stat.dev == stat.dev)
+            }
+            _ => (EntrySize::default(), false),
+        })
+        .reduce(
+            || (EntrySize::default(), true),
+            |(a, a_cacheable), (b, b_cacheable)| (a + b, a_cacheable && b_cacheable),
+        );
+
+    let total = below + size;
+    let cacheable = complete && below_cacheable && listing.linked.is_empty() && !total.incomplete;
+    if let Some(cache) = walk.cache.filter(|_| cacheable) {
+        cache.put(path, stat, &total);
+    }
+    (total, cacheable)
+}
+
+/// The files of one directory as `--deep` counts them: the total of files
+/// with a single link, the hard-linked files (kept apart so they can be
+/// counted once) and the names of the subdirectories.
+#[derive(Default)]
+struct DirListing {
+    apparent: u64,
+    allocated: u64,
+    linked: Vec<LinkedFile>,
+    subdirs: Vec<OsString>,
+}
+
+struct LinkedFile {
+    dev: u64,
+    ino: u64,
+    apparent: u64,
+    allocated: u64,
+}
+
+/// Stats every file of `dir` once. Subdirectories known from the listing's
+/// file type are not stat'ed here, the walk does that when it enters them.
+/// Also returns whether every entry could be read.
+fn read_listing(dir: &DirHandle, opts: &ScanOptions) -> (DirListing, bool) {
+    let Ok(entries) = fs::read_dir(dir.path()) else {
+        return (DirListing::default(), false);
+    };
+
+    let mut listing = DirListing::default();
+    let mut complete = true;
+    let mut files = Vec::new();
+    for entry in entries {
+        let Ok(entry) = entry else {
+            complete = false;
+            continue;
+        };
+        let file_type = entry.file_type().ok();
+        if file_type.is_some_and(|t| t.is_dir()) {
+            listing.subdirs.push(entry.file_name());
+        } else {
+            let follow = opts.follow_symlinks && file_type.is_some_and(|t| t.is_symlink());
+            files.push((entry.file_name(), follow));
+        }
+    }
+
+    let requests: Vec<_> = files
+        .iter()
+        .map(|(name, follow)| (name.as_os_str(), *follow))
+        .collect();
+    let stats = dir.stat_many(&requests, Fields::default());
+
+    for ((name, follow), stat) in files.into_iter().zip(stats) {
+        let stat = match stat {
+            // Broken links still count with their own size
+            Err(_) if follow => dir.stat(&name, false, Fields::default()),
+            stat => stat,
+        };
+        let Ok(stat) = stat else {
+            complete = false;
+            continue;
+        };
+
+        if stat.is_dir() {
+            listing.subdirs.push(name);
+        } else if stat.nlink > 1 {
+            listing.linked.push(LinkedFile {
+                dev: stat.dev,
+                ino: stat.ino,
+                apparent: stat.size,
+                allocated: stat.allocated,
+            });
+        } else {
+            listing.apparent += stat.size;
+            listing.allocated += stat.allocated;
+        }
+    }
+    (listing, complete)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::build_summary;
+    use std::time::Duration;
+
+    /// A directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = env::temp_dir().join(format!("iyanls-test-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn file(&self, name: &str, len: usize) {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![b'x'; len]).unwrap();
+        }
+
+        fn link(&self, original: &str, link: &str) {
+            let link = self.0.join(link);
+            fs::create_dir_all(link.parent().unwrap()).unwrap();
+            fs::hard_link(self.0.join(original), link).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn deep_options(count_links: bool) -> ScanOptions {
+        ScanOptions {
+            pattern: None,
+            include: None,
+            exclude: None,
+            show_line_numbers: false,
+            deep: true,
+            count_links,
+            one_file_system: false,
+            follow_symlinks: false,
+            size_cache: CacheMode::Off,
+            cache_max_age: Duration::ZERO,
+            timeout: None,
+            time_fields: vec![TimeField::Modified],
+            stat_fields: Fields::default(),
+            show_cwd: false,
+            search: None,
+        }
+    }
+
+    fn list(dir: &TempDir, opts: &ScanOptions) -> Vec<FileEntry> {
+        let mut files = Vec::new();
+        get_file(&dir.0, opts, &SizeFormat::default(), &mut |entry| {
+            files.push(entry);
+            ControlFlow::Continue(())
+        });
+        files
+    }
+
+    fn size_of(files: &[FileEntry], name: &str) -> u64 {
+        files.iter().find(|file| file.name == name).unwrap().size
+    }
+
+    #[test]
+    fn hard_links_in_one_directory_count_once() {
+        let dir = TempDir::new("links-within");
+        dir.file("a/x", 3000);
+        dir.link("a/x", "a/x2");
+
+        let files = list(&dir, &deep_options(false));
+        assert_eq!(size_of(&files, "a/"), 3000);
+        assert_eq!(files[0].dedup_links, 1);
+    }
+
+    #[test]
+    fn hard_links_across_entries_count_once_in_the_summary() {
+        let dir = TempDir::new("links-across");
+        dir.file("a/x", 3000);
+        dir.link("a/x", "b/y");
+        dir.link("a/x", "z");
+
+        let files = list(&dir, &deep_options(false));
+        // Each directory still shows what it holds
+        assert_eq!(size_of(&files, "a/"), 3000);
+        assert_eq!(size_of(&files, "b/"), 3000);
+        assert_eq!(size_of(&files, "z"), 3000);
+
+        let summary = build_summary(&files, 0);
+        assert_eq!(summary.apparent_size, 3000);
+        assert_eq!(summary.dedup_links, 2);
+    }
+
+    #[test]
+    fn count_links_counts_every_link() {
+        let dir = TempDir::new("count-links");
+        dir.file("a/x", 3000);
+        dir.link("a/x", "a/x2");
+        dir.link("a/x", "b/y");
+
+        let files = list(&dir, &deep_options(true));
+        assert_eq!(size_of(&files, "a/"), 6000);
+        assert_eq!(size_of(&files, "b/"), 3000);
+
+        let summary = build_summary(&files, 0);
+        assert_eq!(summary.apparent_size, 9000);
+        assert_eq!(summary.dedup_links, 0);
     }
 }
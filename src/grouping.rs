@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+
+use crate::i18n::{self, Msg, tr};
+use crate::types::{DisplayOptions, EntryType, FileEntry, FormatOptions, Group, GroupBy};
+use crate::utils::owner_name;
+
+/// Size groups by their exclusive upper limit
+const SIZE_BUCKETS: &[(u64, Msg)] = &[
+    (1, Msg::SizeEmpty),
+    (1_000, Msg::SizeUnder1Kb),
+    (1_000_000, Msg::Size1KbTo1Mb),
+    (100_000_000, Msg::Size1MbTo100Mb),
+    (1_000_000_000, Msg::Size100MbTo1Gb),
+];
+
+/// Splits `files` into titled groups, keeping the existing order of entries
+/// within each group so that sort flags still apply.
+pub fn group_files(
+    files: &[FileEntry],
+    group_by: &GroupBy,
+    display_opts: &DisplayOptions,
+) -> Vec<Group> {
+    let format = &display_opts.format;
+    let mut owners: HashMap<u32, String> = HashMap::new();
+    let mut groups: BTreeMap<(i64, String), Vec<FileEntry>> = BTreeMap::new();
+    let today = format.timezone.date_naive(Utc::now());
+
+    for file in files {
+        let key = match group_by {
+            GroupBy::Ext => ext_key(file),
+            GroupBy::Type => type_key(&file.e_type),
+            GroupBy::Owner => {
+                let name = owners
+                    .entry(file.uid)
+                    .or_insert_with(|| owner_name(file.uid));
+                (0, name.clone())
+            }
+            GroupBy::Day => day_key(file, format),
+            GroupBy::Calendar => calendar_key(file, today, format),
+            GroupBy::SizeBucket => size_bucket_key(file.listed_size(&display_opts.size_mode)),
+        };
+        groups.entry(key).or_default().push(file.clone());
+    }
+
+    groups
+        .into_iter()
+        .map(|((_, title), mut entries)| {
+            if display_opts.show_line_numbers {
+                for (index, entry) in entries.iter_mut().enumerate() {
+                    entry.line_number = (index + 1).to_string();
+                }
+            }
+            Group {
+                group: title,
+                count: entries.len(),
+                total_size: entries
+                    .iter()
+                    .map(|entry| entry.listed_size(&display_opts.size_mode))
+                    .sum(),
+                entries,
+            }
+        })
+        .collect()
+}
+
+fn ext_key(file: &FileEntry) -> (i64, String) {
+    if matches!(file.e_type, EntryType::Dir) {
+        return (0, title_case(Msg::Directories));
+    }
+
+    let base = file.name.rsplit('/').next().unwrap_or(&file.name);
+    match base.rfind('.') {
+        Some(dot) if dot > 0 => (1, base[dot..].to_lowercase()),
+        _ => (2, tr(Msg::NoExtension).to_string()),
+    }
+}
+
+fn type_key(e_type: &EntryType) -> (i64, String) {
+    match e_type {
+        EntryType::Dir => (0, title_case(Msg::Directories)),
+        EntryType::File => (1, title_case(Msg::Files)),
+        EntryType::Symlink => (2, title_case(Msg::Symlinks)),
+    }
+}
+
+fn day_key(file: &FileEntry, opts: &FormatOptions) -> (i64, String) {
+    let datetime: DateTime<Utc> = file.modified.into();
+    let local = opts.timezone.date_naive(datetime);
+    // Newest day first
+    (
+        -i64::from(local.num_days_from_ce()),
+        local
+            .format(&i18n::localize_format(
+                "%a %b %e %Y",
+                local,
+                opts.time_locale,
+            ))
+            .to_string(),
+    )
+}
+
+/// Buckets by how many days ago the entry was modified: Today, Yesterday,
+/// the weekday names for the rest of the past week, Last week, then one
+/// group per month.
+fn calendar_key(file: &FileEntry, today: NaiveDate, opts: &FormatOptions) -> (i64, String) {
+    let datetime: DateTime<Utc> = file.modified.into();
+    let date = opts.timezone.date_naive(datetime);
+    let days_ago = (today - date).num_days();
+
+    match days_ago {
+        ..0 => (-1, tr(Msg::Future).to_string()),
+        0 => (0, tr(Msg::Today).to_string()),
+        1 => (1, tr(Msg::Yesterday).to_string()),
+        2..7 => (
+            days_ago,
+            date.format(&i18n::localize_format("%A", date, opts.time_locale))
+                .to_string(),
+        ),
+        7..14 => (7, tr(Msg::LastWeek).to_string()),
+        _ => {
+            let months_ago = i64::from(today.year() * 12 + today.month0() as i32)
+                - i64::from(date.year() * 12 + date.month0() as i32);
+            (
+                8 + months_ago,
+                date.format(&i18n::localize_format("%B %Y", date, opts.time_locale))
+                    .to_string(),
+            )
+        }
+    }
+}
+
+fn size_bucket_key(size: u64) -> (i64, String) {
+    for (index, (limit, title)) in SIZE_BUCKETS.iter().enumerate() {
+        if size < *limit {
+            return (index as i64, tr(*title).to_string());
+        }
+    }
+    (SIZE_BUCKETS.len() as i64, tr(Msg::SizeOver1Gb).to_string())
+}
+
+/// "directories" -> "Directories", for group titles built from summary nouns.
+fn title_case(msg: Msg) -> String {
+    let word = tr(msg);
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(size: u64) -> String {
+        size_bucket_key(size).1
+    }
+
+    #[test]
+    fn size_buckets_start_at_their_lower_limit() {
+        assert_eq!(bucket(0), tr(Msg::SizeEmpty));
+        assert_eq!(bucket(1), tr(Msg::SizeUnder1Kb));
+        assert_eq!(bucket(999), tr(Msg::SizeUnder1Kb));
+        assert_eq!(bucket(1_000), tr(Msg::Size1KbTo1Mb));
+        assert_eq!(bucket(999_999), tr(Msg::Size1KbTo1Mb));
+        assert_eq!(bucket(1_000_000), tr(Msg::Size1MbTo100Mb));
+        assert_eq!(bucket(100_000_000), tr(Msg::Size100MbTo1Gb));
+        assert_eq!(bucket(999_999_999), tr(Msg::Size100MbTo1Gb));
+        assert_eq!(bucket(1_000_000_000), tr(Msg::SizeOver1Gb));
+    }
+}
@@ -0,0 +1,372 @@
+use std::{env, sync::OnceLock};
+
+use chrono::{Datelike, NaiveDate};
+
+/// Languages with a translated message catalog and date names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lang {
+    En,
+    Id,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Msg {
+    HeaderName,
+    HeaderType,
+    HeaderPermissions,
+    HeaderSize,
+    HeaderDiskSize,
+    HeaderUsage,
+    HeaderModified,
+    HeaderAccessed,
+    HeaderChanged,
+    HeaderCreated,
+    HeaderMatches,
+    HeaderFirstMatch,
+    TypeFile,
+    TypeDir,
+    TypeSymlink,
+    File,
+    Files,
+    Directory,
+    Directories,
+    Symlink,
+    Symlinks,
+    Entry,
+    Entries,
+    OnDisk,
+    Sparse,
+    Incomplete,
+    ScanInterrupted,
+    ScanTimedOut,
+    Newest,
+    Oldest,
+    HiddenByFilters,
+    HardLinkCountedOnce,
+    HardLinksCountedOnce,
+    NoExtension,
+    SizeEmpty,
+    SizeUnder1Kb,
+    Size1KbTo1Mb,
+    Size1MbTo100Mb,
+    Size100MbTo1Gb,
+    SizeOver1Gb,
+    Today,
+    Yesterday,
+    LastWeek,
+    Future,
+    Unknown,
+    NoFilesMatching,
+    DirectoryEmpty,
+    PathDoesNotExist,
+    ErrorCheckingPath,
+    ErrorWritingJson,
+    JsonExported,
+    MissingIncludeDir,
+    MultipleSortFlags,
+    InvalidTimezone,
+    InvalidIconConfig,
+    CacheNoLocation,
+    CacheLocation,
+    CacheEntries,
+    CachePruned,
+    CacheCleared,
+    CacheWriteFailed,
+    StreamFailed,
+    InvalidContainsPattern,
+}
+
+static MESSAGE_LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Picks the message language from `LC_ALL`, `LC_MESSAGES` or `LANG`.
+/// Must be called before any message is translated.
+pub fn init() {
+    MESSAGE_LANG.get_or_init(|| lang_from_env(&["LC_ALL", "LC_MESSAGES", "LANG"]));
+}
+
+/// Language used for month, weekday and relative time names, taken from
+/// `LC_ALL`, `LC_TIME` or `LANG`.
+pub fn time_lang() -> Lang {
+    lang_from_env(&["LC_ALL", "LC_TIME", "LANG"])
+}
+
+fn lang_from_env(vars: &[&str]) -> Lang {
+    let value = vars
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let code = value.split(['_', '.', '@']).next().unwrap_or("");
+
+    match code {
+        "id" | "in" => Lang::Id,
+        _ => Lang::En,
+    }
+}
+
+pub fn tr(msg: Msg) -> &'static str {
+    let lang = *MESSAGE_LANG.get().unwrap_or(&Lang::En);
+    match lang {
+        Lang::En => english(msg),
+        Lang::Id => indonesian(msg),
+    }
+}
+
+/// Digit group separator for long byte counts.
+pub fn thousands_separator() -> char {
+    match MESSAGE_LANG.get().unwrap_or(&Lang::En) {
+        Lang::En => ',',
+        Lang::Id => '.',
+    }
+}
+
+/// Separator between the whole and fractional part of a number.
+pub fn decimal_separator() -> char {
+    match MESSAGE_LANG.get().unwrap_or(&Lang::En) {
+        Lang::En => '.',
+        Lang::Id => ',',
+    }
+}
+
+/// "1 file", "3 files"
+pub fn count(n: usize, singular: Msg, plural: Msg) -> String {
+    format!("{} {}", n, tr(if n == 1 { singular } else { plural }))
+}
+
+fn english(msg: Msg) -> &'static str {
+    match msg {
+        Msg::HeaderName => "Name",
+        Msg::HeaderType => "Type",
+        Msg::HeaderPermissions => "Permissions",
+        Msg::HeaderSize => "Size",
+        Msg::HeaderDiskSize => "On Disk",
+        Msg::HeaderUsage => "Usage",
+        Msg::HeaderModified => "Modified Date",
+        Msg::HeaderAccessed => "Accessed",
+        Msg::HeaderChanged => "Changed",
+        Msg::HeaderCreated => "Created",
+        Msg::HeaderMatches => "Matches",
+        Msg::HeaderFirstMatch => "First Match",
+        Msg::TypeFile => "File",
+        Msg::TypeDir => "Dir",
+        Msg::TypeSymlink => "Symlink",
+        Msg::File => "file",
+        Msg::Files => "files",
+        Msg::Directory => "directory",
+        Msg::Directories => "directories",
+        Msg::Symlink => "symlink",
+        Msg::Symlinks => "symlinks",
+        Msg::Entry => "entry",
+        Msg::Entries => "entries",
+        Msg::OnDisk => "on disk",
+        Msg::Sparse => "sparse",
+        Msg::Incomplete => "incomplete",
+        Msg::ScanInterrupted => "Interrupted: sizes marked as incomplete are partial.",
+        Msg::ScanTimedOut => "Timed out: sizes marked as incomplete are partial.",
+        Msg::Newest => "Newest",
+        Msg::Oldest => "Oldest",
+        Msg::HiddenByFilters => "hidden by filters",
+        Msg::HardLinkCountedOnce => "hard link counted once",
+        Msg::HardLinksCountedOnce => "hard links counted once",
+        Msg::NoExtension => "No extension",
+        Msg::SizeEmpty => "Empty",
+        Msg::SizeUnder1Kb => "Under 1 KB",
+        Msg::Size1KbTo1Mb => "1 KB – 1 MB",
+        Msg::Size1MbTo100Mb => "1 MB – 100 MB",
+        Msg::Size100MbTo1Gb => "100 MB – 1 GB",
+        Msg::SizeOver1Gb => "Over 1 GB",
+        Msg::Today => "Today",
+        Msg::Yesterday => "Yesterday",
+        Msg::LastWeek => "Last week",
+        Msg::Future => "In the future",
+        Msg::Unknown => "unknown",
+        Msg::NoFilesMatching => "No files found matching the pattern.",
+        Msg::DirectoryEmpty => "Directory is empty.",
+        Msg::PathDoesNotExist => "Path does not exist.",
+        Msg::ErrorCheckingPath => "Error checking path.",
+        Msg::ErrorWritingJson => "Error writing JSON file",
+        Msg::JsonExported => "JSON exported to:",
+        Msg::MissingIncludeDir => "Warning: Directory '{}' does not exist or is not a directory",
+        Msg::MultipleSortFlags => {
+            "Warning: Multiple sort flags specified. Using the first one found."
+        }
+        Msg::InvalidTimezone => "Invalid timezone '{}'. Using the system timezone instead.",
+        Msg::InvalidIconConfig => "Warning: ignoring invalid icon config",
+        Msg::CacheNoLocation => "No cache directory: set XDG_CACHE_HOME or HOME",
+        Msg::CacheLocation => "Cache:",
+        Msg::CacheEntries => "Directories:",
+        Msg::CachePruned => "Removed entries:",
+        Msg::CacheCleared => "Cache cleared.",
+        Msg::CacheWriteFailed => "Error writing cache",
+        Msg::StreamFailed => "Error printing the listing",
+        Msg::InvalidContainsPattern => "Invalid --contains pattern",
+    }
+}
+
+fn indonesian(msg: Msg) -> &'static str {
+    match msg {
+        Msg::HeaderName => "Nama",
+        Msg::HeaderType => "Jenis",
+        Msg::HeaderPermissions => "Izin",
+        Msg::HeaderSize => "Ukuran",
+        Msg::HeaderDiskSize => "Di Disk",
+        Msg::HeaderUsage => "Pemakaian",
+        Msg::HeaderModified => "Tanggal Diubah",
+        Msg::HeaderAccessed => "Diakses",
+        Msg::HeaderChanged => "Diganti",
+        Msg::HeaderCreated => "Dibuat",
+        Msg::HeaderMatches => "Kecocokan",
+        Msg::HeaderFirstMatch => "Kecocokan Pertama",
+        Msg::TypeFile => "Berkas",
+        Msg::TypeDir => "Direktori",
+        Msg::TypeSymlink => "Tautan",
+        Msg::File | Msg::Files => "berkas",
+        Msg::Directory | Msg::Directories => "direktori",
+        Msg::Symlink | Msg::Symlinks => "tautan",
+        Msg::Entry | Msg::Entries => "entri",
+        Msg::OnDisk => "di disk",
+        Msg::Sparse => "renggang",
+        Msg::Incomplete => "belum lengkap",
+        Msg::ScanInterrupted => "Dihentikan: ukuran yang ditandai belum lengkap hanya sebagian.",
+        Msg::ScanTimedOut => "Waktu habis: ukuran yang ditandai belum lengkap hanya sebagian.",
+        Msg::Newest => "Terbaru",
+        Msg::Oldest => "Terlama",
+        Msg::HiddenByFilters => "disembunyikan oleh filter",
+        Msg::HardLinkCountedOnce | Msg::HardLinksCountedOnce => "tautan keras dihitung sekali",
+        Msg::NoExtension => "Tanpa ekstensi",
+        Msg::SizeEmpty => "Kosong",
+        Msg::SizeUnder1Kb => "Di bawah 1 KB",
+        Msg::Size1KbTo1Mb => "1 KB – 1 MB",
+        Msg::Size1MbTo100Mb => "1 MB – 100 MB",
+        Msg::Size100MbTo1Gb => "100 MB – 1 GB",
+        Msg::SizeOver1Gb => "Di atas 1 GB",
+        Msg::Today => "Hari ini",
+        Msg::Yesterday => "Kemarin",
+        Msg::LastWeek => "Minggu lalu",
+        Msg::Future => "Di masa depan",
+        Msg::Unknown => "tidak diketahui",
+        Msg::NoFilesMatching => "Tidak ada berkas yang cocok dengan pola.",
+        Msg::DirectoryEmpty => "Direktori kosong.",
+        Msg::PathDoesNotExist => "Jalur tidak ada.",
+        Msg::ErrorCheckingPath => "Gagal memeriksa jalur.",
+        Msg::ErrorWritingJson => "Gagal menulis berkas JSON",
+        Msg::JsonExported => "JSON diekspor ke:",
+        Msg::MissingIncludeDir => "Peringatan: Direktori '{}' tidak ada atau bukan direktori",
+        Msg::MultipleSortFlags => {
+            "Peringatan: Beberapa opsi pengurutan diberikan. Yang pertama dipakai."
+        }
+        Msg::InvalidTimezone => "Zona waktu '{}' tidak valid. Memakai zona waktu sistem.",
+        Msg::InvalidIconConfig => "Peringatan: konfigurasi ikon tidak valid diabaikan",
+        Msg::CacheNoLocation => "Tidak ada direktori cache: atur XDG_CACHE_HOME atau HOME",
+        Msg::CacheLocation => "Cache:",
+        Msg::CacheEntries => "Direktori:",
+        Msg::CachePruned => "Entri dihapus:",
+        Msg::CacheCleared => "Cache dibersihkan.",
+        Msg::CacheWriteFailed => "Gagal menulis cache",
+        Msg::StreamFailed => "Gagal mencetak daftar",
+        Msg::InvalidContainsPattern => "Pola --contains tidak valid",
+    }
+}
+
+const ID_WEEKDAYS: [&str; 7] = [
+    "Senin", "Selasa", "Rabu", "Kamis", "Jumat", "Sabtu", "Minggu",
+];
+const ID_WEEKDAYS_SHORT: [&str; 7] = ["Sen", "Sel", "Rab", "Kam", "Jum", "Sab", "Min"];
+const ID_MONTHS: [&str; 12] = [
+    "Januari",
+    "Februari",
+    "Maret",
+    "April",
+    "Mei",
+    "Juni",
+    "Juli",
+    "Agustus",
+    "September",
+    "Oktober",
+    "November",
+    "Desember",
+];
+const ID_MONTHS_SHORT: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "Mei", "Jun", "Jul", "Agu", "Sep", "Okt", "Nov", "Des",
+];
+
+/// Replaces the name specifiers (`%a`, `%A`, `%b`, `%h`, `%B`) in a strftime
+/// format with the names for `date` in `lang`, leaving everything else for
+/// chrono. English formats are returned unchanged.
+pub fn localize_format(format: &str, date: NaiveDate, lang: Lang) -> String {
+    if lang == Lang::En {
+        return format.to_string();
+    }
+
+    let weekday = date.weekday().num_days_from_monday() as usize;
+    let month = date.month0() as usize;
+
+    let mut localized = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            localized.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('a') => localized.push_str(ID_WEEKDAYS_SHORT[weekday]),
+            Some('A') => localized.push_str(ID_WEEKDAYS[weekday]),
+            Some('b' | 'h') => localized.push_str(ID_MONTHS_SHORT[month]),
+            Some('B') => localized.push_str(ID_MONTHS[month]),
+            Some(other) => {
+                localized.push('%');
+                localized.push(other);
+            }
+            None => localized.push('%'),
+        }
+    }
+    localized
+}
+
+/// Words used by the relative time format.
+pub struct RelativeWords {
+    pub just_now: &'static str,
+    pub yesterday: &'static str,
+    pub tomorrow: &'static str,
+    pub ago: &'static str,
+    pub in_future: &'static str,
+    /// (singular, plural) for year, month, week, day, hour, min, sec
+    pub units: [(&'static str, &'static str); 7],
+}
+
+pub fn relative_words(lang: Lang) -> RelativeWords {
+    match lang {
+        Lang::En => RelativeWords {
+            just_now: "just now",
+            yesterday: "yesterday",
+            tomorrow: "tomorrow",
+            ago: "{} ago",
+            in_future: "in {}",
+            units: [
+                ("year", "years"),
+                ("month", "months"),
+                ("week", "weeks"),
+                ("day", "days"),
+                ("hour", "hours"),
+                ("min", "min"),
+                ("sec", "sec"),
+            ],
+        },
+        Lang::Id => RelativeWords {
+            just_now: "baru saja",
+            yesterday: "kemarin",
+            tomorrow: "besok",
+            ago: "{} yang lalu",
+            in_future: "dalam {}",
+            units: [
+                ("tahun", "tahun"),
+                ("bulan", "bulan"),
+                ("minggu", "minggu"),
+                ("hari", "hari"),
+                ("jam", "jam"),
+                ("menit", "menit"),
+                ("detik", "detik"),
+            ],
+        },
+    }
+}
@@ -0,0 +1,181 @@
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+
+use crate::i18n::{Msg, tr};
+use crate::types::{EntryType, FileEntry};
+
+const DIRECTORY: &str = "\u{f07b}";
+const FILE: &str = "\u{f15b}";
+const SYMLINK: &str = "\u{f0c1}";
+
+const FILENAMES: &[(&str, &str)] = &[
+    ("Cargo.toml", "\u{e7a8}"),
+    ("Cargo.lock", "\u{e7a8}"),
+    ("Dockerfile", "\u{f308}"),
+    ("docker-compose.yml", "\u{f308}"),
+    ("Makefile", "\u{f489}"),
+    ("LICENSE", "\u{f02d}"),
+    ("README.md", "\u{f02d}"),
+    ("package.json", "\u{e718}"),
+    (".gitignore", "\u{f1d3}"),
+    (".gitattributes", "\u{f1d3}"),
+    (".gitmodules", "\u{f1d3}"),
+];
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "\u{e7a8}"),
+    ("py", "\u{e606}"),
+    ("js", "\u{e74e}"),
+    ("ts", "\u{e628}"),
+    ("go", "\u{e627}"),
+    ("c", "\u{e61e}"),
+    ("h", "\u{e61e}"),
+    ("cpp", "\u{e61d}"),
+    ("java", "\u{e738}"),
+    ("rb", "\u{e739}"),
+    ("php", "\u{e73d}"),
+    ("sh", "\u{f489}"),
+    ("md", "\u{f48a}"),
+    ("json", "\u{e60b}"),
+    ("toml", "\u{e615}"),
+    ("yml", "\u{e615}"),
+    ("yaml", "\u{e615}"),
+    ("html", "\u{e736}"),
+    ("css", "\u{e749}"),
+    ("png", "\u{f1c5}"),
+    ("jpg", "\u{f1c5}"),
+    ("jpeg", "\u{f1c5}"),
+    ("gif", "\u{f1c5}"),
+    ("svg", "\u{f1c5}"),
+    ("webp", "\u{f1c5}"),
+    ("mp3", "\u{f001}"),
+    ("flac", "\u{f001}"),
+    ("wav", "\u{f001}"),
+    ("mp4", "\u{f03d}"),
+    ("mkv", "\u{f03d}"),
+    ("webm", "\u{f03d}"),
+    ("zip", "\u{f410}"),
+    ("tar", "\u{f410}"),
+    ("gz", "\u{f410}"),
+    ("xz", "\u{f410}"),
+    ("7z", "\u{f410}"),
+    ("rar", "\u{f410}"),
+    ("pdf", "\u{f1c1}"),
+    ("lock", "\u{f023}"),
+    ("txt", "\u{f15c}"),
+];
+
+#[derive(Debug, Clone)]
+pub struct IconTheme {
+    directory: String,
+    file: String,
+    symlink: String,
+    filenames: HashMap<String, String>,
+    extensions: HashMap<String, String>,
+}
+
+/// Partial theme read from `icons.json`; anything left out keeps its default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct IconOverrides {
+    directory: Option<String>,
+    file: Option<String>,
+    symlink: Option<String>,
+    filenames: HashMap<String, String>,
+    extensions: HashMap<String, String>,
+}
+
+impl IconTheme {
+    pub fn load() -> IconTheme {
+        let mut theme = IconTheme::default();
+
+        let Some(path) = config_path() else {
+            return theme;
+        };
+        let Ok(content) = fs::read_to_string(&path) else {
+            return theme;
+        };
+
+        match serde_json::from_str::<IconOverrides>(&content) {
+            Ok(overrides) => theme.apply(overrides),
+            Err(e) => eprintln!(
+                "{} {}: {}",
+                tr(Msg::InvalidIconConfig).yellow(),
+                path.display(),
+                e
+            ),
+        }
+        theme
+    }
+
+    fn apply(&mut self, overrides: IconOverrides) {
+        if let Some(directory) = overrides.directory {
+            self.directory = directory;
+        }
+        if let Some(file) = overrides.file {
+            self.file = file;
+        }
+        if let Some(symlink) = overrides.symlink {
+            self.symlink = symlink;
+        }
+        self.filenames.extend(overrides.filenames);
+        self.extensions.extend(
+            overrides
+                .extensions
+                .into_iter()
+                .map(|(ext, icon)| (ext.trim_start_matches('.').to_lowercase(), icon)),
+        );
+    }
+
+    pub fn icon_for(&self, file: &FileEntry) -> &str {
+        let base = file
+            .name
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(&file.name);
+
+        if let Some(icon) = self.filenames.get(base) {
+            return icon;
+        }
+
+        match file.e_type {
+            EntryType::Dir => &self.directory,
+            EntryType::Symlink => &self.symlink,
+            EntryType::File => base
+                .rfind('.')
+                .filter(|dot| *dot > 0)
+                .and_then(|dot| self.extensions.get(&base[dot + 1..].to_lowercase()))
+                .unwrap_or(&self.file),
+        }
+    }
+}
+
+impl Default for IconTheme {
+    fn default() -> IconTheme {
+        let to_map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, icon)| (key.to_string(), icon.to_string()))
+                .collect()
+        };
+
+        IconTheme {
+            directory: DIRECTORY.to_string(),
+            file: FILE.to_string(),
+            symlink: SYMLINK.to_string(),
+            filenames: to_map(FILENAMES),
+            extensions: to_map(EXTENSIONS),
+        }
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("iyanls").join("icons.json"))
+}
@@ -1,31 +1,72 @@
 use clap::Parser;
 use owo_colors::OwoColorize;
-use std::{fs, path::PathBuf, process::exit};
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    ops::ControlFlow,
+    path::PathBuf,
+    process::exit,
+    time::Duration,
+};
 use termimad::MadSkin;
 
+mod cache;
 mod cli;
 mod display;
 mod file_ops;
+mod grouping;
+mod i18n;
+mod icons;
+mod progress;
+mod search;
 mod sorting;
+mod stat;
+mod stream;
+mod summary;
 mod types;
 mod utils;
 
-use cli::Args;
-use display::{export_json, print_table_from_files};
+use cli::{Args, Command};
+use display::{
+    export_json, listing_to_csv, listing_to_json, name_line, print_groups, print_summary,
+    print_table_from_files,
+};
 use file_ops::get_file;
-use sorting::{get_sort_order, sort_files};
+use grouping::group_files;
+use i18n::{Msg, tr};
+use icons::IconTheme;
+use search::ContentSearch;
+use sorting::{ExternalSorter, get_sort_order, sort_files};
 use std::os::unix::io::AsRawFd;
-use types::FileEntry;
+use stream::{Finished, Listing, RowFormat};
+use summary::build_summary;
+use types::{
+    CacheMode, DisplayOptions, FileEntry, FormatOptions, GroupBy, Heatmap, Hyperlink, NameFit,
+    ScanOptions, ScanResult, SizeBar, SizeFormat, StopReason, TimeField,
+};
 
 const DOCS_MD: &str = include_str!("../docs.md");
 
 fn main() {
     let tty_available = unsafe { libc::isatty(std::io::stdout().as_raw_fd()) == 1 };
+    i18n::init();
     let args = Args::parse();
     let sort_order = get_sort_order(&args);
 
-    let path = args.path.unwrap_or(PathBuf::from("."));
-    let timezone = utils::parse_timezone(&args.timezone);
+    let path = args.path.clone().unwrap_or(PathBuf::from("."));
+    let timezone = utils::parse_timezone(args.timezone.as_deref());
+
+    if let Some(Command::Cache { action }) = &args.command {
+        cache::run(action);
+        exit(0);
+    }
+
+    if let Some(threads) = args.threads {
+        // Only fails if the pool was already started, which cannot happen here
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.into())
+            .build_global();
+    }
 
     if args.print_docs {
         let skin = MadSkin::default_dark();
@@ -35,54 +76,270 @@ fn main() {
 
     if let Ok(exists) = fs::exists(&path) {
         if exists {
-            let mut files = get_file(
-                &path,
-                &args.grab,
-                &args.include,
-                &args.exclude,
-                !args.no_line_numbers,
-                args.octal_perms,
-                args.owner_perms,
-                &args.time_format,
-                &timezone,
-                &args.custom_time_format,
-                args.deep,
-                args.toggle_clock,
-                args.show_cwd,
-            );
-
-            if let Some(order) = sort_order {
-                sort_files(&mut files, &order);
+            let scan_opts = ScanOptions {
+                pattern: args.grab.clone(),
+                include: args.include.clone(),
+                exclude: args.exclude.clone(),
+                show_line_numbers: !args.no_line_numbers,
+                deep: args.deep,
+                count_links: args.count_links,
+                one_file_system: args.one_file_system,
+                follow_symlinks: args.follow_symlinks,
+                timeout: args.timeout.map(Duration::from_secs),
+                size_cache: if args.no_cache {
+                    CacheMode::Off
+                } else if args.refresh {
+                    CacheMode::Refresh
+                } else {
+                    CacheMode::Use
+                },
+                cache_max_age: Duration::from_secs(args.cache_max_age),
+                time_fields: args.time_field.clone(),
+                stat_fields: stat::Fields {
+                    // CSV and JSON always carry uid and gid
+                    owner: args.owner_perms
+                        || matches!(args.group_by, Some(GroupBy::Owner))
+                        || args.csv
+                        || args.json
+                        || args.json_export.is_some(),
+                    accessed: args.time_field.contains(&TimeField::Accessed),
+                    created: args.time_field.contains(&TimeField::Created),
+                },
+                show_cwd: args.show_cwd,
+                search: args.contains.as_ref().map(|pattern| {
+                    ContentSearch::new(
+                        pattern,
+                        args.regex,
+                        args.binary,
+                        args.max_search_size,
+                        !args.no_ignore,
+                    )
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}: {}", tr(Msg::InvalidContainsPattern).red(), e);
+                        exit(1);
+                    })
+                }),
+            };
+            let format_opts = FormatOptions {
+                octal_perms: args.octal_perms,
+                owner_perms: args.owner_perms,
+                time_format: args.time_format.clone(),
+                timezone,
+                custom_time_format: args.custom_time_format.clone(),
+                time_style: args.time_style.clone(),
+                toggle_clock: args.toggle_clock,
+                relative_precision: args.relative_precision as usize,
+                time_locale: i18n::time_lang(),
+                size_format: SizeFormat {
+                    units: args.size_units.clone(),
+                    block_size: if args.kibibytes {
+                        utils::parse_block_size("1K").ok()
+                    } else {
+                        args.block_size.clone()
+                    },
+                    precision: args.size_precision.map(usize::from),
+                },
+            };
+            if args.deep {
+                progress::install_interrupt_handler();
+            }
+
+            let hyperlink_host = match args.hyperlink {
+                Hyperlink::Always => true,
+                Hyperlink::Auto => tty_available,
+                Hyperlink::Never => false,
             }
+            .then(utils::hostname);
+            let names_only = !tty_available && !args.json && !args.csv;
+            let group_by = if args.by_day {
+                Some(GroupBy::Calendar)
+            } else {
+                args.group_by.clone()
+            };
+
+            let mut display_opts = DisplayOptions {
+                show_line_numbers: !args.no_line_numbers,
+                time_fields: args.time_field.clone(),
+                name_fit: if args.no_truncate {
+                    NameFit::Full
+                } else if args.wrap_names {
+                    NameFit::Wrap
+                } else {
+                    NameFit::Truncate
+                },
+                size_bar: None,
+                age_heatmap: matches!(args.heatmap, Some(Heatmap::Age | Heatmap::All))
+                    .then(|| args.age_thresholds.0.clone()),
+                size_heatmap: matches!(args.heatmap, Some(Heatmap::Size | Heatmap::All))
+                    .then(|| args.size_thresholds.0.clone()),
+                size_mode: args.size_mode.clone(),
+                format: format_opts,
+                icons: args.icons.then(IconTheme::load),
+                hyperlink_host,
+                show_matches: args.contains.is_some(),
+            };
+
+            // Outputs that need every entry at once (JSON, groups, size bars)
+            // and --deep, whose sizing dominates anyway, are collected whole
+            let streamable = !args.json
+                && args.json_export.is_none()
+                && group_by.is_none()
+                && args.size_bar.is_none()
+                && !args.deep;
+
+            let mut files = Vec::new();
+            let ScanResult { hidden, stopped } = if streamable {
+                let format = if names_only {
+                    RowFormat::Names(display_opts.hyperlink_host.as_deref())
+                } else if args.csv {
+                    RowFormat::Csv(&args.time_field, args.contains.is_some())
+                } else {
+                    RowFormat::Table(&display_opts)
+                };
+                let sorter = sort_order.clone().map(|order| {
+                    ExternalSorter::new(
+                        order,
+                        args.time_field[0],
+                        args.size_mode.clone(),
+                        args.sort_memory,
+                    )
+                });
+                let mut listing = Listing::new(format, sorter);
+                let scan = get_file(
+                    &path,
+                    &scan_opts,
+                    &display_opts.format.size_format,
+                    &mut |entry| listing.push(entry),
+                );
+
+                match listing.finish() {
+                    Ok(Finished::Collected(entries)) => files = entries,
+                    Ok(Finished::Streamed(mut summary)) => {
+                        if args.summary && !names_only && !args.csv {
+                            summary.hidden = scan.hidden;
+                            check_output(print_summary(
+                                &mut io::stdout().lock(),
+                                &summary,
+                                &display_opts.format,
+                            ));
+                        }
+                        exit(0);
+                    }
+                    Err(e) => output_failed(e),
+                }
+                scan
+            } else {
+                let scan = get_file(
+                    &path,
+                    &scan_opts,
+                    &display_opts.format.size_format,
+                    &mut |entry| {
+                        files.push(entry);
+                        ControlFlow::Continue(())
+                    },
+                );
+                if let Some(order) = &sort_order {
+                    sort_files(&mut files, order, &args.time_field[0], &args.size_mode);
+                }
+                scan
+            };
 
             if !args.no_line_numbers {
                 add_line_numbers(&mut files);
             }
 
-            if !tty_available {
-                print_names_only(&files);
+            let mut out = BufWriter::new(io::stdout().lock());
+            if names_only {
+                check_output(
+                    print_names_only(&mut out, &files, display_opts.hyperlink_host.as_deref())
+                        .and_then(|()| out.flush()),
+                );
                 exit(0);
             }
 
-            if args.json {
-                println!("{}", serde_json::to_string_pretty(&files).unwrap());
+            display_opts.size_bar = args.size_bar.as_ref().map(|scale| {
+                let sizes = files.iter().map(|file| file.listed_size(&args.size_mode));
+                match scale {
+                    SizeBar::Max => sizes.max().unwrap_or(0),
+                    SizeBar::Total => sizes.sum(),
+                }
+            });
+
+            let summary = args.summary.then(|| build_summary(&files, hidden));
+            let groups = group_by.map(|group_by| group_files(&files, &group_by, &display_opts));
+
+            let json_content = (args.json || args.json_export.is_some()).then(|| {
+                match &groups {
+                    Some(groups) => listing_to_json(groups, true, summary.as_ref()),
+                    None => listing_to_json(&files, false, summary.as_ref()),
+                }
+                .unwrap()
+            });
+
+            let printed = (|| {
+                if args.csv {
+                    write!(
+                        out,
+                        "{}",
+                        listing_to_csv(
+                            &files,
+                            groups.as_deref(),
+                            &args.time_field,
+                            args.contains.is_some()
+                        )
+                    )?;
+                } else if let Some(json_content) = json_content.as_ref().filter(|_| args.json) {
+                    writeln!(out, "{}", json_content)?;
+                } else {
+                    match &groups {
+                        Some(groups) if !groups.is_empty() => {
+                            print_groups(&mut out, groups, &display_opts)?
+                        }
+                        _ => print_table_from_files(
+                            &mut out,
+                            &files,
+                            &args.grab.clone().or_else(|| args.contains.clone()),
+                            &display_opts,
+                        )?,
+                    }
+                    if let Some(summary) = &summary {
+                        print_summary(&mut out, summary, &display_opts.format)?;
+                    }
+                }
+                out.flush()
+            })();
+
+            // The export does not depend on anyone reading stdout
+            if let (Some(export_path), Some(json_content)) = (&args.json_export, &json_content) {
+                if let Err(e) = export_json(json_content, export_path) {
+                    eprintln!("{}: {}", tr(Msg::ErrorWritingJson).red(), e);
+                    exit(1);
+                }
+                check_output(printed.and_then(|()| {
+                    writeln!(
+                        out,
+                        "{} {}",
+                        tr(Msg::JsonExported).green(),
+                        export_path.display()
+                    )?;
+                    out.flush()
+                }));
             } else {
-                print_table_from_files(&files, &args.grab, !args.no_line_numbers);
+                check_output(printed);
             }
 
-            if let Some(export_path) = &args.json_export {
-                if let Err(e) = export_json(&files, export_path) {
-                    eprintln!("{}: {}", "Error writing JSON file".red(), e);
-                    exit(1);
+            if let Some(reason) = &stopped {
+                progress::print_stop_warning(reason);
+                if *reason == StopReason::Interrupted {
+                    exit(130);
                 }
-                println!("{} {}", "JSON exported to:".green(), export_path.display());
             }
         } else {
-            eprintln!("{}", "Path does not exist.".red());
+            eprintln!("{}", tr(Msg::PathDoesNotExist).red());
             exit(1);
         }
     } else {
-        eprintln!("{}", "Error checking path.".red());
+        eprintln!("{}", tr(Msg::ErrorCheckingPath).red());
         exit(1);
     }
 }
@@ -93,8 +350,28 @@ fn add_line_numbers(files: &mut [FileEntry]) {
     }
 }
 
-fn print_names_only(files: &[FileEntry]) {
+fn print_names_only(
+    out: &mut impl Write,
+    files: &[FileEntry],
+    hyperlink_host: Option<&str>,
+) -> io::Result<()> {
     for file in files {
-        println!("{}", file.name);
+        writeln!(out, "{}", name_line(file, hyperlink_host))?;
+    }
+    Ok(())
+}
+
+fn check_output(result: io::Result<()>) {
+    if let Err(e) = result {
+        output_failed(e);
+    }
+}
+
+/// A reader that went away, as with `| head`, is not an error.
+fn output_failed(e: io::Error) -> ! {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        exit(0);
     }
+    eprintln!("{}: {}", tr(Msg::StreamFailed).red(), e);
+    exit(1);
 }
@@ -0,0 +1,156 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use owo_colors::OwoColorize;
+
+use crate::i18n::{Msg, count, tr};
+use crate::types::{SizeFormat, StopReason};
+use crate::utils::{display_width, format_decimal, format_size, stderr_width, truncate_middle};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    // A second Ctrl-C gives up on printing partial results
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Lets the first Ctrl-C stop `--deep` sizing instead of killing the
+/// process, so that whatever was counted so far can still be shown.
+pub fn install_interrupt_handler() {
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Counters for one `--deep` scan, shared by the walk and the progress line.
+pub struct Progress {
+    dirs: AtomicU64,
+    bytes: AtomicU64,
+    current: Mutex<String>,
+    /// Set by the progress line when it is ready for a new `current`, so
+    /// that the walk only copies a path about once per redraw
+    wants_current: AtomicBool,
+    started: Instant,
+    deadline: Option<Instant>,
+    timed_out: AtomicBool,
+}
+
+impl Progress {
+    pub fn new(timeout: Option<Duration>) -> Progress {
+        let started = Instant::now();
+        Progress {
+            dirs: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            current: Mutex::new(String::new()),
+            wants_current: AtomicBool::new(false),
+            started,
+            // Too far away to ever be reached when it overflows
+            deadline: timeout.and_then(|timeout| started.checked_add(timeout)),
+            timed_out: AtomicBool::new(false),
+        }
+    }
+
+    pub fn enter_dir(&self, path: &Path) {
+        self.dirs.fetch_add(1, Ordering::Relaxed);
+        // Loaded first so that walker threads do not all write the flag
+        if self.wants_current.load(Ordering::Relaxed)
+            && self.wants_current.swap(false, Ordering::Relaxed)
+        {
+            *self.current.lock().unwrap() = path.to_string_lossy().into_owned();
+        }
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// True once the user pressed Ctrl-C or `--timeout` ran out.
+    pub fn should_stop(&self) -> bool {
+        if INTERRUPTED.load(Ordering::Relaxed) || self.timed_out.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.timed_out.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            Some(StopReason::Interrupted)
+        } else if self.timed_out.load(Ordering::Relaxed) {
+            Some(StopReason::TimedOut)
+        } else {
+            None
+        }
+    }
+
+    /// Redraws the progress line on stderr until `done` is set, then clears
+    /// it. Does nothing when stderr is not a terminal. The caller unparks
+    /// this thread once done so that short scans are not held up.
+    pub fn report_until(&self, done: &AtomicBool, size_format: &SizeFormat) {
+        let mut stderr = io::stderr();
+        if !stderr.is_terminal() {
+            return;
+        }
+
+        let mut drawn = false;
+        self.wants_current.store(true, Ordering::Relaxed);
+        loop {
+            thread::park_timeout(REFRESH_INTERVAL);
+            if done.load(Ordering::Relaxed) {
+                break;
+            }
+            drawn = true;
+            let status = format!(
+                "{} · {} · {}s · ",
+                count(
+                    self.dirs.load(Ordering::Relaxed) as usize,
+                    Msg::Directory,
+                    Msg::Directories
+                ),
+                format_size(self.bytes.load(Ordering::Relaxed), size_format).trim_start(),
+                format_decimal(self.started.elapsed().as_secs_f64(), 1),
+            );
+            let width = stderr_width().unwrap_or(80).saturating_sub(1);
+            let current = truncate_middle(
+                &self.current.lock().unwrap(),
+                width.saturating_sub(display_width(&status)).max(1),
+            );
+            self.wants_current.store(true, Ordering::Relaxed);
+            let _ = write!(stderr, "\r\x1b[K{}{}", status, current);
+            let _ = stderr.flush();
+        }
+        if drawn {
+            let _ = write!(stderr, "\r\x1b[K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+pub fn print_stop_warning(reason: &StopReason) {
+    let message = match reason {
+        StopReason::Interrupted => tr(Msg::ScanInterrupted),
+        StopReason::TimedOut => tr(Msg::ScanTimedOut),
+    };
+    eprintln!("{}", message.yellow());
+}
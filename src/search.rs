@@ -0,0 +1,437 @@
+use std::{ffi::OsStr, fs, io::Read, os::unix::ffi::OsStrExt, path::Path};
+
+use rayon::prelude::*;
+use regex::bytes::Regex;
+
+use crate::types::{ContentMatch, EntryType, FileEntry};
+
+/// Bytes looked at for a NUL when telling binary files apart, as git does
+const BINARY_PROBE: usize = 8000;
+/// Longest first matching line kept, in characters
+const MAX_LINE_CHARS: usize = 200;
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// The `--contains` search run on every listed file.
+#[derive(Debug, Clone)]
+pub struct ContentSearch {
+    regex: Regex,
+    /// Also search files that look binary
+    pub binary: bool,
+    /// Files larger than this are skipped
+    pub max_size: u64,
+    /// Skip files matched by `.gitignore` and `.ignore`
+    pub ignore_files: bool,
+}
+
+impl ContentSearch {
+    /// `pattern` is taken literally unless `is_regex` is set.
+    pub fn new(
+        pattern: &str,
+        is_regex: bool,
+        binary: bool,
+        max_size: u64,
+        ignore_files: bool,
+    ) -> Result<ContentSearch, regex::Error> {
+        let regex = if is_regex {
+            Regex::new(pattern)?
+        } else {
+            Regex::new(&regex::escape(pattern))?
+        };
+        Ok(ContentSearch {
+            regex,
+            binary,
+            max_size,
+            ignore_files,
+        })
+    }
+
+    /// Keeps the entries whose contents match, with their match filled in.
+    /// Directories and files that are skipped or cannot be read are dropped.
+    pub fn filter(&self, entries: Vec<FileEntry>) -> Vec<FileEntry> {
+        entries
+            .into_par_iter()
+            .filter_map(|mut entry| {
+                // Symlinks count when they point at a file
+                if matches!(entry.e_type, EntryType::Dir) || entry.name.ends_with('/') {
+                    return None;
+                }
+                entry.content_match = Some(self.search(&entry.path, entry.size)?);
+                Some(entry)
+            })
+            .collect()
+    }
+
+    fn search(&self, path: &Path, size: u64) -> Option<ContentMatch> {
+        if size > self.max_size {
+            return None;
+        }
+        // Look at the first chunk alone so skipped binaries cost one small
+        // read
+        let mut file = fs::File::open(path).ok()?.take(self.max_size);
+        let mut contents = Vec::new();
+        (&mut file)
+            .take(BINARY_PROBE as u64)
+            .read_to_end(&mut contents)
+            .ok()?;
+        if !self.binary && contents.contains(&0) {
+            return None;
+        }
+        file.read_to_end(&mut contents).ok()?;
+
+        let mut found: Option<ContentMatch> = None;
+        for (index, line) in contents.split(|byte| *byte == b'\n').enumerate() {
+            if !self.regex.is_match(line) {
+                continue;
+            }
+            match &mut found {
+                Some(found) => found.count += 1,
+                None => {
+                    let text = String::from_utf8_lossy(line);
+                    found = Some(ContentMatch {
+                        count: 1,
+                        line: index + 1,
+                        // Tabs and other control characters would break
+                        // the table
+                        text: text
+                            .trim()
+                            .chars()
+                            .take(MAX_LINE_CHARS)
+                            .map(|c| if c.is_control() { ' ' } else { c })
+                            .collect(),
+                    });
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Patterns from the `.gitignore` and `.ignore` files of a directory and,
+/// inside a repository, of its parents up to the repository root. Only
+/// entries of the directory itself are checked, so listing an ignored
+/// directory still searches it.
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    pattern: Vec<u8>,
+    /// Path from the ignore file's directory to the listed one, ending in
+    /// `/` unless empty
+    prefix: Vec<u8>,
+    /// Matched against the whole path instead of just the name
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl IgnoreRules {
+    pub fn load(dir: &Path) -> IgnoreRules {
+        let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        // Outside a repository, files above the listed directory do not apply
+        let levels = match dir
+            .ancestors()
+            .position(|ancestor| ancestor.join(".git").exists())
+        {
+            Some(root) => dir.ancestors().take(root + 1).collect(),
+            None => vec![dir.as_path()],
+        };
+
+        // Outermost first, so that closer files override them
+        let mut rules = Vec::new();
+        for base in levels.into_iter().rev() {
+            let prefix = relative_prefix(base, &dir);
+            for file in IGNORE_FILES {
+                if let Ok(text) = fs::read(base.join(file)) {
+                    rules.extend(
+                        text.split(|byte| *byte == b'\n')
+                            .filter_map(|line| Rule::parse(line, &prefix)),
+                    );
+                }
+            }
+        }
+        IgnoreRules { rules }
+    }
+
+    /// The last matching rule wins, as in git.
+    pub fn is_ignored(&self, name: &OsStr, is_dir: bool) -> bool {
+        let name = name.as_bytes();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(name, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+fn relative_prefix(base: &Path, dir: &Path) -> Vec<u8> {
+    let relative = dir.strip_prefix(base).unwrap_or(Path::new(""));
+    let mut prefix = relative.as_os_str().as_bytes().to_vec();
+    if !prefix.is_empty() {
+        prefix.push(b'/');
+    }
+    prefix
+}
+
+impl Rule {
+    fn parse(line: &[u8], prefix: &[u8]) -> Option<Rule> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line = line.trim_ascii_end();
+        if line.is_empty() || line.starts_with(b"#") {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix(b"!") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // A leading backslash escapes `#` or `!`
+        let line = line.strip_prefix(b"\\").unwrap_or(line);
+        let (dir_only, line) = match line.strip_suffix(b"/") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains(&b'/');
+        let pattern = line.strip_prefix(b"/").unwrap_or(line);
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(Rule {
+            pattern: pattern.to_vec(),
+            prefix: prefix.to_vec(),
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    fn matches(&self, name: &[u8], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.pattern, &[self.prefix.as_slice(), name].concat())
+        } else {
+            glob_match(&self.pattern, name)
+        }
+    }
+}
+
+/// Matches gitignore globs: `*` and `?` stay within one path component,
+/// `**` also crosses `/`, and `[...]` takes ranges and `!` or `^` negation.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => match rest.strip_prefix(b"/") {
+            // `**/` stands for any number of whole components
+            Some(rest) => (0..=text.len())
+                .filter(|&skip| skip == 0 || text[skip - 1] == b'/')
+                .any(|skip| glob_match(rest, &text[skip..])),
+            None => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        },
+        [b'*', rest @ ..] => {
+            let component = text.iter().position(|b| *b == b'/').unwrap_or(text.len());
+            (0..=component).any(|skip| glob_match(rest, &text[skip..]))
+        }
+        [b'?', rest @ ..] => {
+            matches!(text.first(), Some(b) if *b != b'/') && glob_match(rest, &text[1..])
+        }
+        [b'[', rest @ ..] => match (text.first(), class_match(rest, text.first())) {
+            (Some(_), Some((true, after))) => glob_match(after, &text[1..]),
+            (_, Some((false, _))) | (None, Some(_)) => false,
+            // No closing bracket: a literal `[`
+            (_, None) => text.first() == Some(&b'[') && glob_match(rest, &text[1..]),
+        },
+        [b'\\', literal, rest @ ..] | [literal, rest @ ..] => {
+            text.first() == Some(literal) && glob_match(rest, &text[1..])
+        }
+    }
+}
+
+/// Matches `byte` against a bracket class whose `[` was already taken.
+/// Returns whether it matched and the pattern after the class, or None
+/// when the class is not closed.
+fn class_match<'a>(class: &'a [u8], byte: Option<&u8>) -> Option<(bool, &'a [u8])> {
+    let (negated, class) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    // A `]` right after the opening bracket is part of the class
+    let end = class
+        .iter()
+        .skip(1)
+        .position(|b| *b == b']')
+        .map(|position| position + 1)?;
+    let (items, after) = (&class[..end], &class[end + 1..]);
+
+    let Some(&byte) = byte else {
+        return Some((false, after));
+    };
+    let mut found = false;
+    let mut index = 0;
+    while index < items.len() {
+        if index + 2 < items.len() && items[index + 1] == b'-' {
+            found |= (items[index]..=items[index + 2]).contains(&byte);
+            index += 3;
+        } else {
+            found |= items[index] == byte;
+            index += 1;
+        }
+    }
+    Some((found != negated && byte != b'/', after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(line: &str) -> Rule {
+        Rule::parse(line.as_bytes(), b"").unwrap()
+    }
+
+    fn rules(lines: &[&str]) -> IgnoreRules {
+        IgnoreRules {
+            rules: lines.iter().map(|line| rule(line)).collect(),
+        }
+    }
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        glob_match(pattern.as_bytes(), text.as_bytes())
+    }
+
+    #[test]
+    fn parse_skips_blank_lines_and_comments() {
+        assert!(Rule::parse(b"", b"").is_none());
+        assert!(Rule::parse(b"   ", b"").is_none());
+        assert!(Rule::parse(b"# comment", b"").is_none());
+        assert!(Rule::parse(b"!", b"").is_none());
+        assert!(Rule::parse(b"/", b"").is_none());
+    }
+
+    #[test]
+    fn parse_reads_flags() {
+        let negated = rule("!keep.log");
+        assert!(negated.negated);
+        assert_eq!(negated.pattern, b"keep.log");
+
+        let dir_only = rule("build/");
+        assert!(dir_only.dir_only && !dir_only.anchored);
+        assert_eq!(dir_only.pattern, b"build");
+
+        let anchored = rule("/sub/secret.txt");
+        assert!(anchored.anchored && !anchored.dir_only);
+        assert_eq!(anchored.pattern, b"sub/secret.txt");
+        assert!(rule("/top").anchored);
+
+        let escaped = rule("\\#not-a-comment");
+        assert_eq!(escaped.pattern, b"#not-a-comment");
+        let escaped = rule("\\!literal");
+        assert!(!escaped.negated);
+        assert_eq!(escaped.pattern, b"!literal");
+
+        assert_eq!(rule("crlf.txt  \r").pattern, b"crlf.txt");
+    }
+
+    #[test]
+    fn star_and_question_mark_stay_in_one_component() {
+        assert!(glob("*.log", "x.log"));
+        assert!(glob("*.log", ".log"));
+        assert!(!glob("*.log", "x.log.gz"));
+        assert!(!glob("*.log", "dir/x.log"));
+        assert!(glob("gen_?.txt", "gen_1.txt"));
+        assert!(!glob("gen_?.txt", "gen_10.txt"));
+        assert!(!glob("a?b", "a/b"));
+        assert!(glob("\\*", "*"));
+        assert!(!glob("\\*", "a"));
+    }
+
+    #[test]
+    fn double_star_crosses_components() {
+        assert!(glob("**/foo", "foo"));
+        assert!(glob("**/foo", "a/b/foo"));
+        assert!(!glob("**/foo", "afoo"));
+        assert!(glob("a/**", "a/b/c"));
+        assert!(glob("a/**/b", "a/b"));
+        assert!(glob("a/**/b", "a/x/y/b"));
+        assert!(!glob("a/**/b", "a/xb"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(glob("[abc].txt", "b.txt"));
+        assert!(!glob("[abc].txt", "d.txt"));
+        assert!(glob("file[0-9]", "file7"));
+        assert!(!glob("file[0-9]", "filex"));
+        assert!(glob("[!a-c]", "d"));
+        assert!(!glob("[!a-c]", "a"));
+        assert!(glob("[^0-9]", "x"));
+        assert!(glob("[]]", "]"));
+        assert!(!glob("[!x]", "/"));
+        assert!(!glob("[a]", ""));
+        // Without a closing bracket the `[` is literal
+        assert!(glob("[abc", "[abc"));
+        assert!(!glob("[abc", "a"));
+    }
+
+    #[test]
+    fn class_match_returns_the_rest_of_the_pattern() {
+        assert_eq!(
+            class_match(b"a-c]rest", Some(&b'b')),
+            Some((true, &b"rest"[..]))
+        );
+        assert_eq!(class_match(b"!a-c]", Some(&b'b')), Some((false, &b""[..])));
+        assert_eq!(class_match(b"]]x", Some(&b']')), Some((true, &b"x"[..])));
+        assert_eq!(class_match(b"abc]", None), Some((false, &b""[..])));
+        assert_eq!(class_match(b"abc", Some(&b'a')), None);
+    }
+
+    #[test]
+    fn anchored_rules_match_from_the_ignore_file() {
+        let secret = Rule::parse(b"/sub/secret.txt", b"sub/").unwrap();
+        assert!(secret.matches(b"secret.txt", false));
+        let other = Rule::parse(b"/sub/secret.txt", b"").unwrap();
+        assert!(!other.matches(b"secret.txt", false));
+        // Unanchored rules match the name at any depth
+        let log = Rule::parse(b"*.log", b"deep/down/").unwrap();
+        assert!(log.matches(b"x.log", false));
+    }
+
+    #[test]
+    fn dir_only_rules_skip_files() {
+        let build = rules(&["build/"]);
+        assert!(build.is_ignored(OsStr::new("build"), true));
+        assert!(!build.is_ignored(OsStr::new("build"), false));
+    }
+
+    #[test]
+    fn later_negation_wins() {
+        let logs = rules(&["*.log", "!keep.log"]);
+        assert!(logs.is_ignored(OsStr::new("x.log"), false));
+        assert!(!logs.is_ignored(OsStr::new("keep.log"), false));
+        assert!(!logs.is_ignored(OsStr::new("x.txt"), false));
+
+        let again = rules(&["*.log", "!keep.log", "keep.*"]);
+        assert!(again.is_ignored(OsStr::new("keep.log"), false));
+    }
+
+    #[test]
+    fn binary_check_looks_at_the_first_chunk() {
+        let path = std::env::temp_dir().join(format!("iyanls-test-{}-binary", std::process::id()));
+        let search = |contents: &[u8], binary: bool| {
+            fs::write(&path, contents).unwrap();
+            let search = ContentSearch::new("needle", false, binary, u64::MAX, false).unwrap();
+            search
+                .search(&path, contents.len() as u64)
+                .map(|found| found.line)
+        };
+
+        assert_eq!(search(b"\0\nneedle\n", false), None);
+        assert_eq!(search(b"\0\nneedle\n", true), Some(2));
+
+        let mut late_nul = vec![b'x'; BINARY_PROBE];
+        late_nul.extend_from_slice(b"\0\nneedle\n");
+        assert_eq!(search(&late_nul, false), Some(2));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,6 +1,25 @@
 use crate::cli::Args;
-use crate::types::{FileEntry, SortOrder};
+use crate::i18n::{Msg, tr};
+use crate::types::{
+    ContentMatch, EntryType, FileEntry, SharedLinks, SizeMode, SortOrder, TimeField,
+};
 use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    env,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Lines, Write},
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Most runs read at once by a merge, to stay well within the open file
+/// limit. More runs are first merged into fewer, longer ones.
+const MAX_FAN_IN: usize = 64;
 
 pub fn get_sort_order(args: &Args) -> Option<SortOrder> {
     let sort_flags = [
@@ -20,56 +39,523 @@ pub fn get_sort_order(args: &Args) -> Option<SortOrder> {
         0 => None,
         1 => Some(active_sorts[0].1.clone()),
         _ => {
-            eprintln!(
-                "{}",
-                "Warning: Multiple sort flags specified. Using the first one found.".yellow()
-            );
+            eprintln!("{}", tr(Msg::MultipleSortFlags).yellow());
             Some(active_sorts[0].1.clone())
         }
     }
 }
 
-pub fn sort_files(files: &mut Vec<FileEntry>, sort_order: &SortOrder) {
+/// Sorts `files` in place. Time-based orders use `time_field`; entries
+/// without that timestamp (e.g. unknown birth time) count as the oldest.
+/// Size orders use the size listed under `size_mode`.
+pub fn sort_files(
+    files: &mut [FileEntry],
+    sort_order: &SortOrder,
+    time_field: &TimeField,
+    size_mode: &SizeMode,
+) {
+    files.sort_by(|a, b| compare(a, b, sort_order, time_field, size_mode));
+}
+
+fn compare(
+    a: &FileEntry,
+    b: &FileEntry,
+    sort_order: &SortOrder,
+    time_field: &TimeField,
+    size_mode: &SizeMode,
+) -> Ordering {
+    let name = |file: &FileEntry| file.name.trim_end_matches('/').to_lowercase();
+    let is_dir = |file: &FileEntry| file.name.ends_with('/');
+
     match sort_order {
-        SortOrder::UpToDate => {
-            files.sort_by(|a, b| b.raw_modified.cmp(&a.raw_modified));
+        SortOrder::UpToDate => b.time(time_field).cmp(&a.time(time_field)),
+        SortOrder::DownToDate => a.time(time_field).cmp(&b.time(time_field)),
+        SortOrder::LargestSize => b.listed_size(size_mode).cmp(&a.listed_size(size_mode)),
+        SortOrder::SmallestSize => a.listed_size(size_mode).cmp(&b.listed_size(size_mode)),
+        SortOrder::AlphabeticalOrder => name(a).cmp(&name(b)),
+        SortOrder::AlphabeticalReverse => name(b).cmp(&name(a)),
+        SortOrder::DirFirst => is_dir(b).cmp(&is_dir(a)),
+        SortOrder::DirLast => is_dir(a).cmp(&is_dir(b)),
+    }
+}
+
+/// Sorts a listing that may not fit in memory. Entries are buffered until
+/// they take about `memory_limit` bytes, then that run is sorted and written
+/// to a temporary file; `finish` merges the runs back in order, in several
+/// passes when there are more than `MAX_FAN_IN` of them. Like
+/// `sort_files`, entries that compare equal keep their scan order.
+pub struct ExternalSorter {
+    sort_order: SortOrder,
+    time_field: TimeField,
+    size_mode: SizeMode,
+    memory_limit: usize,
+    buffer: Vec<FileEntry>,
+    buffered_bytes: usize,
+    runs: Vec<SpillFile>,
+}
+
+impl ExternalSorter {
+    pub fn new(
+        sort_order: SortOrder,
+        time_field: TimeField,
+        size_mode: SizeMode,
+        memory_limit: u64,
+    ) -> ExternalSorter {
+        ExternalSorter {
+            sort_order,
+            time_field,
+            size_mode,
+            memory_limit: usize::try_from(memory_limit).unwrap_or(usize::MAX),
+            buffer: Vec::new(),
+            buffered_bytes: 0,
+            runs: Vec::new(),
         }
-        SortOrder::DownToDate => {
-            files.sort_by(|a, b| a.raw_modified.cmp(&b.raw_modified));
+    }
+
+    pub fn push(&mut self, entry: FileEntry) -> io::Result<()> {
+        self.buffered_bytes += entry_bytes(&entry);
+        self.buffer.push(entry);
+        if self.buffered_bytes >= self.memory_limit {
+            self.spill()?;
         }
-        SortOrder::LargestSize => {
-            files.sort_by(|a, b| b.raw_size.cmp(&a.raw_size));
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.sort_buffer();
+        let run = SpillFile::write(self.buffer.drain(..).map(Ok))?;
+        self.runs.push(run);
+        self.buffered_bytes = 0;
+        Ok(())
+    }
+
+    fn sort_buffer(&mut self) {
+        sort_files(
+            &mut self.buffer,
+            &self.sort_order,
+            &self.time_field,
+            &self.size_mode,
+        );
+    }
+
+    /// Returns every pushed entry in order. Without any spilled run this is
+    /// just the sorted buffer.
+    pub fn finish(mut self) -> io::Result<SortedEntries> {
+        self.sort_buffer();
+        if self.runs.is_empty() {
+            return Ok(SortedEntries::Memory(self.buffer.into_iter()));
         }
-        SortOrder::SmallestSize => {
-            files.sort_by(|a, b| a.raw_size.cmp(&b.raw_size));
+
+        // One source is left for the buffer
+        while self.runs.len() >= MAX_FAN_IN {
+            self.merge_pass()?;
         }
-        SortOrder::AlphabeticalOrder => {
-            files.sort_by(|a, b| {
-                let name_a = a.name.trim_end_matches('/');
-                let name_b = b.name.trim_end_matches('/');
-                name_a.to_lowercase().cmp(&name_b.to_lowercase())
-            });
+        let runs = std::mem::take(&mut self.runs);
+        let buffer = std::mem::take(&mut self.buffer);
+        Ok(SortedEntries::Merge(self.merge(runs, buffer)?))
+    }
+
+    /// Merges each group of `MAX_FAN_IN` consecutive runs into one. Runs
+    /// stay in scan order, so the merges that follow are still stable.
+    fn merge_pass(&mut self) -> io::Result<()> {
+        let mut rest = std::mem::take(&mut self.runs);
+        while !rest.is_empty() {
+            let tail = rest.split_off(rest.len().min(MAX_FAN_IN));
+            let group = std::mem::replace(&mut rest, tail);
+            let merged = SpillFile::write(self.merge(group, Vec::new())?)?;
+            self.runs.push(merged);
         }
-        SortOrder::AlphabeticalReverse => {
-            files.sort_by(|a, b| {
-                let name_a = a.name.trim_end_matches('/');
-                let name_b = b.name.trim_end_matches('/');
-                name_b.to_lowercase().cmp(&name_a.to_lowercase())
-            });
+        Ok(())
+    }
+
+    fn merge(&self, runs: Vec<SpillFile>, buffer: Vec<FileEntry>) -> io::Result<Merge> {
+        let mut sources = Vec::new();
+        for run in &runs {
+            sources.push(RunSource::File(
+                BufReader::new(File::open(&run.path)?).lines(),
+            ));
         }
-        SortOrder::DirFirst => {
-            files.sort_by(|a, b| {
-                let is_dir_a = a.name.ends_with('/');
-                let is_dir_b = b.name.ends_with('/');
-                is_dir_b.cmp(&is_dir_a)
-            });
+        // The buffer holds the newest entries, so it merges last
+        sources.push(RunSource::Memory(buffer.into_iter()));
+
+        let mut heads = Vec::new();
+        for source in &mut sources {
+            heads.push(source.next().transpose()?);
+        }
+        Ok(Merge {
+            sort_order: self.sort_order.clone(),
+            time_field: self.time_field,
+            size_mode: self.size_mode.clone(),
+            sources,
+            heads,
+            _runs: runs,
+        })
+    }
+}
+
+/// Rough heap footprint of an entry, to decide when to spill.
+fn entry_bytes(entry: &FileEntry) -> usize {
+    std::mem::size_of::<FileEntry>()
+        + entry.line_number.capacity()
+        + entry.name.capacity()
+        + entry.path.as_os_str().len()
+}
+
+pub enum SortedEntries {
+    Memory(std::vec::IntoIter<FileEntry>),
+    Merge(Merge),
+}
+
+impl Iterator for SortedEntries {
+    type Item = io::Result<FileEntry>;
+
+    fn next(&mut self) -> Option<io::Result<FileEntry>> {
+        match self {
+            SortedEntries::Memory(entries) => entries.next().map(Ok),
+            SortedEntries::Merge(merge) => merge.next(),
         }
-        SortOrder::DirLast => {
-            files.sort_by(|a, b| {
-                let is_dir_a = a.name.ends_with('/');
-                let is_dir_b = b.name.ends_with('/');
-                is_dir_a.cmp(&is_dir_b)
+    }
+}
+
+/// K-way merge of at most `MAX_FAN_IN` sorted runs, so the smallest head is
+/// found with a linear scan; taking the first of equal heads keeps the sort
+/// stable since runs are in scan order.
+pub struct Merge {
+    sort_order: SortOrder,
+    time_field: TimeField,
+    size_mode: SizeMode,
+    sources: Vec<RunSource>,
+    heads: Vec<Option<FileEntry>>,
+    _runs: Vec<SpillFile>,
+}
+
+impl Iterator for Merge {
+    type Item = io::Result<FileEntry>;
+
+    fn next(&mut self) -> Option<io::Result<FileEntry>> {
+        let mut smallest: Option<usize> = None;
+        for (index, head) in self.heads.iter().enumerate() {
+            let Some(head) = head else {
+                continue;
+            };
+            let is_smaller = smallest.is_none_or(|current| {
+                let current = self.heads[current].as_ref().unwrap();
+                compare(
+                    head,
+                    current,
+                    &self.sort_order,
+                    &self.time_field,
+                    &self.size_mode,
+                ) == Ordering::Less
             });
+            if is_smaller {
+                smallest = Some(index);
+            }
+        }
+
+        let index = smallest?;
+        let next = match self.sources[index].next().transpose() {
+            Ok(next) => next,
+            Err(e) => return Some(Err(e)),
+        };
+        std::mem::replace(&mut self.heads[index], next).map(Ok)
+    }
+}
+
+enum RunSource {
+    File(Lines<BufReader<File>>),
+    Memory(std::vec::IntoIter<FileEntry>),
+}
+
+impl RunSource {
+    fn next(&mut self) -> Option<io::Result<FileEntry>> {
+        match self {
+            RunSource::File(lines) => Some(lines.next()?.and_then(|line| {
+                let entry: SpilledEntry = serde_json::from_str(&line)?;
+                Ok(entry.into())
+            })),
+            RunSource::Memory(entries) => entries.next().map(Ok),
+        }
+    }
+}
+
+/// A temporary run file, removed when dropped.
+struct SpillFile {
+    path: PathBuf,
+}
+
+/// Numbers the run files of every sorter in this process
+static NEXT_SPILL: AtomicUsize = AtomicUsize::new(0);
+
+impl SpillFile {
+    /// Writes already sorted entries to a new run file.
+    fn write(entries: impl Iterator<Item = io::Result<FileEntry>>) -> io::Result<SpillFile> {
+        let path = env::temp_dir().join(format!(
+            "iyanls-sort-{}-{}.jsonl",
+            std::process::id(),
+            NEXT_SPILL.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+        // Fail rather than overwrite or follow something already there
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        // Removes the file if writing fails
+        let run = SpillFile { path };
+        let mut writer = BufWriter::new(file);
+        for entry in entries {
+            serde_json::to_writer(&mut writer, &SpilledEntry::from(entry?))?;
+            writer.write_all(b"\n")?;
         }
+        writer.flush()?;
+        Ok(run)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `FileEntry` as written to a run file. Unlike the JSON output it keeps
+/// every field losslessly: paths as raw bytes and times as nanoseconds
+/// from the epoch, which may be negative.
+#[derive(Serialize, Deserialize)]
+struct SpilledEntry {
+    line_number: String,
+    name: String,
+    e_type: EntryType,
+    path: Vec<u8>,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    disk_size: u64,
+    dedup_links: u64,
+    shared: SharedLinks,
+    sparse: bool,
+    incomplete: bool,
+    modified: i128,
+    accessed: Option<i128>,
+    changed: Option<i128>,
+    created: Option<i128>,
+    content_match: Option<ContentMatch>,
+}
+
+impl From<FileEntry> for SpilledEntry {
+    fn from(entry: FileEntry) -> SpilledEntry {
+        SpilledEntry {
+            line_number: entry.line_number,
+            name: entry.name,
+            e_type: entry.e_type,
+            path: entry.path.into_os_string().into_vec(),
+            mode: entry.mode,
+            uid: entry.uid,
+            gid: entry.gid,
+            size: entry.size,
+            disk_size: entry.disk_size,
+            dedup_links: entry.dedup_links,
+            shared: entry.shared,
+            sparse: entry.sparse,
+            incomplete: entry.incomplete,
+            modified: to_nanos(entry.modified),
+            accessed: entry.accessed.map(to_nanos),
+            changed: entry.changed.map(to_nanos),
+            created: entry.created.map(to_nanos),
+            content_match: entry.content_match,
+        }
+    }
+}
+
+impl From<SpilledEntry> for FileEntry {
+    fn from(entry: SpilledEntry) -> FileEntry {
+        FileEntry {
+            line_number: entry.line_number,
+            name: entry.name,
+            e_type: entry.e_type,
+            path: PathBuf::from(OsString::from_vec(entry.path)),
+            mode: entry.mode,
+            uid: entry.uid,
+            gid: entry.gid,
+            size: entry.size,
+            disk_size: entry.disk_size,
+            dedup_links: entry.dedup_links,
+            shared: entry.shared,
+            sparse: entry.sparse,
+            incomplete: entry.incomplete,
+            modified: from_nanos(entry.modified),
+            accessed: entry.accessed.map(from_nanos),
+            changed: entry.changed.map(from_nanos),
+            created: entry.created.map(from_nanos),
+            content_match: entry.content_match,
+        }
+    }
+}
+
+fn to_nanos(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128),
+    }
+}
+
+fn from_nanos(nanos: i128) -> SystemTime {
+    let abs = nanos.unsigned_abs();
+    let offset = Duration::new((abs / 1_000_000_000) as u64, (abs % 1_000_000_000) as u32);
+    if nanos >= 0 {
+        UNIX_EPOCH + offset
+    } else {
+        UNIX_EPOCH - offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64) -> FileEntry {
+        FileEntry {
+            line_number: String::new(),
+            name: name.to_string(),
+            e_type: EntryType::File,
+            path: PathBuf::from(name),
+            mode: 0o100644,
+            uid: 1000,
+            gid: 1000,
+            size,
+            disk_size: size,
+            dedup_links: 0,
+            shared: SharedLinks::default(),
+            sparse: false,
+            incomplete: false,
+            modified: UNIX_EPOCH,
+            accessed: None,
+            changed: None,
+            created: None,
+            content_match: None,
+        }
+    }
+
+    fn sort_externally(entries: Vec<FileEntry>, memory_limit: u64) -> Vec<FileEntry> {
+        let mut sorter = ExternalSorter::new(
+            SortOrder::LargestSize,
+            TimeField::Modified,
+            SizeMode::Apparent,
+            memory_limit,
+        );
+        for entry in entries {
+            sorter.push(entry).unwrap();
+        }
+        sorter.finish().unwrap().collect::<io::Result<_>>().unwrap()
+    }
+
+    /// Sizes repeat so that stability shows in the order of the names
+    fn listing(count: usize) -> Vec<FileEntry> {
+        (0..count)
+            .map(|index| entry(&format!("f{index}"), (index * 7 % 5) as u64))
+            .collect()
+    }
+
+    fn names(entries: &[FileEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn spilled_runs_merge_like_an_in_memory_sort() {
+        let mut expected = listing(305);
+        sort_files(
+            &mut expected,
+            &SortOrder::LargestSize,
+            &TimeField::Modified,
+            &SizeMode::Apparent,
+        );
+        let one_entry = entry_bytes(&entry("f000", 0)) as u64;
+
+        // About a run every ten entries, with the last few left in memory
+        let merged = sort_externally(listing(305), one_entry * 10);
+        assert_eq!(names(&merged), names(&expected));
+        // A run per entry is more than one merge can take at once
+        let merged = sort_externally(listing(305), 1);
+        assert_eq!(names(&merged), names(&expected));
+    }
+
+    #[test]
+    fn equal_entries_keep_scan_order_across_runs() {
+        let entries: Vec<_> = (0..(MAX_FAN_IN * 3))
+            .map(|index| entry(&format!("f{index:03}"), 1))
+            .collect();
+        let expected: Vec<_> = entries.iter().map(|entry| entry.name.clone()).collect();
+        let merged = sort_externally(entries, 1);
+        assert_eq!(names(&merged), expected);
+    }
+
+    #[test]
+    fn unspilled_entries_stay_in_memory() {
+        let mut sorter = ExternalSorter::new(
+            SortOrder::SmallestSize,
+            TimeField::Modified,
+            SizeMode::Apparent,
+            u64::MAX,
+        );
+        for entry in listing(20) {
+            sorter.push(entry).unwrap();
+        }
+        assert!(sorter.runs.is_empty());
+        assert!(matches!(sorter.finish().unwrap(), SortedEntries::Memory(_)));
+    }
+
+    #[test]
+    fn spilled_entry_round_trip_keeps_every_field() {
+        let mut original = entry("name", 42);
+        original.line_number = "7".to_string();
+        original.path = PathBuf::from(OsString::from_vec(b"dir/\xff\xfe".to_vec()));
+        original.disk_size = 4096;
+        original.dedup_links = 3;
+        original.shared = SharedLinks {
+            links: 1,
+            apparent: 10,
+            allocated: 4096,
+        };
+        original.sparse = true;
+        original.incomplete = true;
+        original.modified = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        original.accessed = Some(UNIX_EPOCH - Duration::new(86_400, 1));
+        original.changed = Some(UNIX_EPOCH);
+        original.content_match = Some(ContentMatch {
+            count: 2,
+            line: 5,
+            text: "hello".to_string(),
+        });
+
+        let line = serde_json::to_string(&SpilledEntry::from(original.clone())).unwrap();
+        let restored: FileEntry = serde_json::from_str::<SpilledEntry>(&line).unwrap().into();
+
+        assert_eq!(restored.line_number, original.line_number);
+        assert_eq!(restored.name, original.name);
+        assert_eq!(restored.e_type.to_string(), original.e_type.to_string());
+        assert_eq!(restored.path, original.path);
+        assert_eq!(restored.mode, original.mode);
+        assert_eq!(restored.uid, original.uid);
+        assert_eq!(restored.gid, original.gid);
+        assert_eq!(restored.size, original.size);
+        assert_eq!(restored.disk_size, original.disk_size);
+        assert_eq!(restored.dedup_links, original.dedup_links);
+        let shared = restored.shared;
+        assert_eq!(
+            (shared.links, shared.apparent, shared.allocated),
+            (1, 10, 4096)
+        );
+        assert_eq!(restored.sparse, original.sparse);
+        assert_eq!(restored.incomplete, original.incomplete);
+        assert_eq!(restored.modified, original.modified);
+        assert_eq!(restored.accessed, original.accessed);
+        assert_eq!(restored.changed, original.changed);
+        assert_eq!(restored.created, None);
+        let found = restored.content_match.unwrap();
+        assert_eq!(
+            (found.count, found.line, found.text.as_str()),
+            (2, 5, "hello")
+        );
     }
 }
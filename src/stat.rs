@@ -0,0 +1,406 @@
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rayon::prelude::*;
+
+/// The parts of a file's metadata that listings use. On Linux it is filled
+/// by a single `statx` call relative to the open parent directory, asking
+/// the filesystem only for the fields that will be shown.
+#[derive(Debug, Clone, Copy)]
+pub struct Stat {
+    /// File type and permission bits, as in `st_mode`
+    pub mode: u32,
+    pub size: u64,
+    /// Allocated size in bytes
+    pub allocated: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub dev: u64,
+    pub ino: u64,
+    pub nlink: u64,
+    pub modified: SystemTime,
+    pub accessed: SystemTime,
+    pub changed: SystemTime,
+    /// Birth time, when the filesystem records it and it was asked for
+    pub created: Option<SystemTime>,
+    /// mtime and ctime as seconds and nanoseconds, for the size cache
+    pub stamp: [i64; 4],
+}
+
+/// Optional fields to request on top of type, mode, size, blocks, inode,
+/// link count, mtime and ctime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fields {
+    pub owner: bool,
+    pub accessed: bool,
+    pub created: bool,
+}
+
+impl Stat {
+    pub fn is_dir(&self) -> bool {
+        self.mode & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & libc::S_IFMT == libc::S_IFREG
+    }
+
+    /// Device and inode, identifying the file across hard links.
+    pub fn inode(&self) -> (u64, u64) {
+        (self.dev, self.ino)
+    }
+
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &fs::Metadata) -> Stat {
+        use std::os::unix::fs::MetadataExt;
+
+        Stat {
+            mode: metadata.mode(),
+            size: metadata.len(),
+            // st_blocks is always counted in 512-byte units
+            allocated: metadata.blocks() * 512,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            nlink: metadata.nlink(),
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+            accessed: metadata.accessed().unwrap_or(UNIX_EPOCH),
+            changed: system_time(metadata.ctime(), metadata.ctime_nsec() as u32),
+            created: metadata.created().ok(),
+            stamp: [
+                metadata.mtime(),
+                metadata.mtime_nsec(),
+                metadata.ctime(),
+                metadata.ctime_nsec(),
+            ],
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn from_statx(buf: &libc::statx) -> Stat {
+        let time = |ts: &libc::statx_timestamp| system_time(ts.tv_sec, ts.tv_nsec);
+
+        Stat {
+            mode: u32::from(buf.stx_mode),
+            size: buf.stx_size,
+            allocated: buf.stx_blocks * 512,
+            uid: buf.stx_uid,
+            gid: buf.stx_gid,
+            dev: libc::makedev(buf.stx_dev_major, buf.stx_dev_minor),
+            ino: buf.stx_ino,
+            nlink: u64::from(buf.stx_nlink),
+            modified: time(&buf.stx_mtime),
+            accessed: time(&buf.stx_atime),
+            changed: time(&buf.stx_ctime),
+            created: (buf.stx_mask & libc::STATX_BTIME != 0).then(|| time(&buf.stx_btime)),
+            stamp: [
+                buf.stx_mtime.tv_sec,
+                i64::from(buf.stx_mtime.tv_nsec),
+                buf.stx_ctime.tv_sec,
+                i64::from(buf.stx_ctime.tv_nsec),
+            ],
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Fields {
+    pub fn statx_mask(&self) -> u32 {
+        let mut mask = libc::STATX_TYPE
+            | libc::STATX_MODE
+            | libc::STATX_NLINK
+            | libc::STATX_INO
+            | libc::STATX_SIZE
+            | libc::STATX_BLOCKS
+            | libc::STATX_MTIME
+            | libc::STATX_CTIME;
+        if self.owner {
+            mask |= libc::STATX_UID | libc::STATX_GID;
+        }
+        if self.accessed {
+            mask |= libc::STATX_ATIME;
+        }
+        if self.created {
+            mask |= libc::STATX_BTIME;
+        }
+        mask
+    }
+}
+
+fn system_time(secs: i64, nanos: u32) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nanos)
+    } else {
+        UNIX_EPOCH - Duration::new(secs.unsigned_abs(), 0) + Duration::new(0, nanos)
+    }
+}
+
+/// Stats a path on its own, for roots such as the listed directory.
+pub fn stat_path(path: &Path, follow: bool) -> io::Result<Stat> {
+    let metadata = if follow {
+        fs::metadata(path)
+    } else {
+        fs::symlink_metadata(path)
+    }?;
+    Ok(Stat::from_metadata(&metadata))
+}
+
+/// An open directory whose entries can be stat'ed by name, without the
+/// kernel resolving the full path again for every entry.
+pub struct DirHandle {
+    path: PathBuf,
+    #[cfg(target_os = "linux")]
+    fd: Option<std::os::fd::OwnedFd>,
+}
+
+impl DirHandle {
+    pub fn open(path: &Path) -> DirHandle {
+        DirHandle {
+            path: path.to_path_buf(),
+            #[cfg(target_os = "linux")]
+            fd: linux::open_dir(path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stats the entry `name`, following a symlink when `follow` is set.
+    pub fn stat(&self, name: &OsStr, follow: bool, fields: Fields) -> io::Result<Stat> {
+        #[cfg(target_os = "linux")]
+        if let Some(fd) = &self.fd
+            && let Some(result) = linux::statx_at(fd, name, follow, fields)
+        {
+            return result;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = fields;
+
+        stat_path(&self.path.join(name), follow)
+    }
+
+    /// Stats several entries, each following symlinks when its flag is set,
+    /// and returns the results in the same order. With the `io-uring`
+    /// feature the calls are submitted in batches, which saves a syscall
+    /// round trip per entry in directories with many small files.
+    pub fn stat_many(&self, requests: &[(&OsStr, bool)], fields: Fields) -> Vec<io::Result<Stat>> {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(fd) = &self.fd
+            && uring::available()
+        {
+            return requests
+                .par_chunks(uring::BATCH_SIZE)
+                .flat_map_iter(|chunk| {
+                    uring::statx_batch(fd, chunk, fields).unwrap_or_else(|| {
+                        chunk
+                            .iter()
+                            .map(|&(name, follow)| self.stat(name, follow, fields))
+                            .collect()
+                    })
+                })
+                .collect();
+        }
+
+        requests
+            .par_iter()
+            .map(|&(name, follow)| self.stat(name, follow, fields))
+            .collect()
+    }
+}
+
+/// Batched `statx` through a small io_uring per thread. Rings that cannot
+/// be set up, because the kernel is too old or io_uring is disabled by a
+/// sysctl or seccomp policy, switch every thread back to plain calls.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring {
+    use std::{
+        cell::RefCell,
+        ffi::{CString, OsStr},
+        io,
+        os::{
+            fd::{AsRawFd, OwnedFd},
+            unix::ffi::OsStrExt,
+        },
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use io_uring::{IoUring, Probe, opcode, types};
+
+    use super::{Fields, Stat};
+
+    pub const BATCH_SIZE: usize = 256;
+
+    static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+    thread_local! {
+        static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+    }
+
+    pub fn available() -> bool {
+        !UNAVAILABLE.load(Ordering::Relaxed)
+    }
+
+    fn new_ring() -> Option<IoUring> {
+        let ring = IoUring::new(BATCH_SIZE as u32).ok()?;
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe).ok()?;
+        probe.is_supported(opcode::Statx::CODE).then_some(ring)
+    }
+
+    /// Returns None when io_uring cannot be used, so the caller can fall
+    /// back to one call per entry.
+    pub fn statx_batch(
+        dir: &OwnedFd,
+        requests: &[(&OsStr, bool)],
+        fields: Fields,
+    ) -> Option<Vec<io::Result<Stat>>> {
+        RING.with(|cell| {
+            let mut slot = cell.borrow_mut();
+            if slot.is_none() {
+                let Some(ring) = new_ring() else {
+                    UNAVAILABLE.store(true, Ordering::Relaxed);
+                    return None;
+                };
+                *slot = Some(ring);
+            }
+            let ring = slot.as_mut()?;
+
+            // The kernel writes into these until every completion is reaped,
+            // so they must not move or be dropped before then
+            let names: Vec<Option<CString>> = requests
+                .iter()
+                .map(|(name, _)| CString::new(name.as_bytes()).ok())
+                .collect();
+            let mut bufs: Vec<libc::statx> = vec![unsafe { std::mem::zeroed() }; requests.len()];
+            let mut results: Vec<Option<io::Result<Stat>>> =
+                (0..requests.len()).map(|_| None).collect();
+
+            let mut pending = 0;
+            for (index, ((_, follow), name)) in requests.iter().zip(&names).enumerate() {
+                let Some(name) = name else {
+                    results[index] = Some(Err(io::ErrorKind::InvalidInput.into()));
+                    continue;
+                };
+                let flags = if *follow {
+                    0
+                } else {
+                    libc::AT_SYMLINK_NOFOLLOW
+                };
+                let entry = opcode::Statx::new(
+                    types::Fd(dir.as_raw_fd()),
+                    name.as_ptr(),
+                    (&raw mut bufs[index]).cast::<types::statx>(),
+                )
+                .flags(flags | libc::AT_STATX_SYNC_AS_STAT)
+                .mask(fields.statx_mask())
+                .build()
+                .user_data(index as u64);
+                // Batches are never larger than the ring, so this has room
+                unsafe { ring.submission().push(&entry) }
+                    .expect("statx batch is larger than the ring");
+                pending += 1;
+            }
+
+            while pending > 0 {
+                match ring.submit_and_wait(pending) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => {
+                        // Requests may still be in flight, so give up on
+                        // this ring and its buffers rather than free them
+                        UNAVAILABLE.store(true, Ordering::Relaxed);
+                        std::mem::forget(slot.take());
+                        std::mem::forget(names);
+                        std::mem::forget(bufs);
+                        return None;
+                    }
+                }
+                for cqe in ring.completion() {
+                    let index = cqe.user_data() as usize;
+                    results[index] = Some(if cqe.result() < 0 {
+                        Err(io::Error::from_raw_os_error(-cqe.result()))
+                    } else {
+                        Ok(Stat::from_statx(&bufs[index]))
+                    });
+                    pending -= 1;
+                }
+            }
+
+            Some(results.into_iter().map(Option::unwrap).collect())
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        ffi::{CString, OsStr},
+        io,
+        os::{
+            fd::{AsRawFd, FromRawFd, OwnedFd},
+            unix::ffi::OsStrExt,
+        },
+        path::Path,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use super::{Fields, Stat};
+
+    /// Set once statx fails with ENOSYS (old kernels, some sandboxes)
+    static STATX_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+    pub fn open_dir(path: &Path) -> Option<OwnedFd> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        (fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Returns None when statx is unavailable, so the caller can fall back
+    /// to a path-based stat.
+    pub fn statx_at(
+        dir: &OwnedFd,
+        name: &OsStr,
+        follow: bool,
+        fields: Fields,
+    ) -> Option<io::Result<Stat>> {
+        if STATX_UNSUPPORTED.load(Ordering::Relaxed) {
+            return None;
+        }
+        let Ok(name) = CString::new(name.as_bytes()) else {
+            return Some(Err(io::ErrorKind::InvalidInput.into()));
+        };
+
+        let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+        let mut buf: libc::statx = unsafe { std::mem::zeroed() };
+        let rc = unsafe {
+            libc::statx(
+                dir.as_raw_fd(),
+                name.as_ptr(),
+                flags | libc::AT_STATX_SYNC_AS_STAT,
+                fields.statx_mask(),
+                &mut buf,
+            )
+        };
+        if rc == 0 {
+            return Some(Ok(Stat::from_statx(&buf)));
+        }
+
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOSYS) {
+            STATX_UNSUPPORTED.store(true, Ordering::Relaxed);
+            return None;
+        }
+        Some(Err(err))
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, BufWriter, StdoutLock, Write};
+use std::ops::ControlFlow;
+
+use crate::display::{StreamedTable, csv_header, csv_row, name_line};
+use crate::sorting::ExternalSorter;
+use crate::types::{DisplayOptions, FileEntry, Summary, TimeField};
+
+/// Listings with up to this many entries are rendered whole as before;
+/// larger ones are printed as they come, with table columns sized by
+/// this many rows.
+pub const FIRST_BATCH: usize = 1000;
+
+pub enum RowFormat<'a> {
+    /// Names only, as printed when stdout is not a terminal
+    Names(Option<&'a str>),
+    /// CSV rows, with the `--contains` columns if set
+    Csv(&'a [TimeField], bool),
+    Table(&'a DisplayOptions),
+}
+
+pub enum Finished {
+    /// The listing was small enough to be kept, and is sorted if asked to
+    Collected(Vec<FileEntry>),
+    /// The listing was already printed; only its summary is left
+    Streamed(Summary),
+}
+
+/// Takes entries as the scan emits them. Unsorted entries past the first
+/// batch are printed right away; sorted ones go through an
+/// `ExternalSorter` and are printed from its output. Either way, only
+/// listings of at most `FIRST_BATCH` entries are kept in memory whole.
+pub struct Listing<'a> {
+    format: RowFormat<'a>,
+    sorter: Option<ExternalSorter>,
+    error: Option<io::Error>,
+    entries: Vec<FileEntry>,
+    writer: Option<RowWriter<'a>>,
+    count: usize,
+    summary: Summary,
+}
+
+impl<'a> Listing<'a> {
+    pub fn new(format: RowFormat<'a>, sorter: Option<ExternalSorter>) -> Listing<'a> {
+        Listing {
+            format,
+            sorter,
+            error: None,
+            entries: Vec::new(),
+            writer: None,
+            count: 0,
+            summary: Summary::default(),
+        }
+    }
+
+    /// Breaks once a spill or write failed, including when the output was
+    /// closed, so that the scan can stop. The error is reported by `finish`.
+    pub fn push(&mut self, entry: FileEntry) -> ControlFlow<()> {
+        self.count += 1;
+        self.summary.add(&entry);
+        if self.error.is_some() {
+            return ControlFlow::Break(());
+        }
+
+        let result = match (&mut self.sorter, &mut self.writer) {
+            (Some(sorter), _) => sorter.push(entry),
+            (None, Some(writer)) => writer.write(&entry),
+            (None, None) => {
+                self.entries.push(entry);
+                if self.entries.len() > FIRST_BATCH {
+                    let entries = std::mem::take(&mut self.entries);
+                    RowWriter::start(&self.format, &entries, None)
+                        .map(|writer| self.writer = Some(writer))
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        if let Err(e) = result {
+            self.error = Some(e);
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Fails on the first error writing temporary files or the output; a
+    /// closed output shows up as `ErrorKind::BrokenPipe`.
+    pub fn finish(self) -> io::Result<Finished> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        let writer = match self.sorter {
+            Some(sorter) => {
+                let mut sorted = sorter.finish()?;
+                if self.count <= FIRST_BATCH {
+                    return Ok(Finished::Collected(sorted.collect::<io::Result<_>>()?));
+                }
+                let first = sorted
+                    .by_ref()
+                    .take(FIRST_BATCH)
+                    .collect::<io::Result<Vec<_>>>()?;
+                let mut writer = RowWriter::start(&self.format, &first, Some(self.count))?;
+                for entry in sorted {
+                    writer.write(&entry?)?;
+                }
+                writer
+            }
+            None => match self.writer {
+                Some(writer) => writer,
+                None => return Ok(Finished::Collected(self.entries)),
+            },
+        };
+        writer.finish()?;
+        Ok(Finished::Streamed(self.summary))
+    }
+}
+
+enum RowWriter<'a> {
+    Names(Option<&'a str>, BufWriter<StdoutLock<'static>>),
+    Csv(&'a [TimeField], bool, BufWriter<StdoutLock<'static>>),
+    Table(StreamedTable<'a>),
+}
+
+impl<'a> RowWriter<'a> {
+    /// Starts the output with the `first` entries. `total` is the number of
+    /// entries in the whole listing, if known.
+    fn start(
+        format: &RowFormat<'a>,
+        first: &[FileEntry],
+        total: Option<usize>,
+    ) -> io::Result<RowWriter<'a>> {
+        let out = || BufWriter::new(io::stdout().lock());
+        let mut writer = match *format {
+            RowFormat::Names(host) => RowWriter::Names(host, out()),
+            RowFormat::Csv(time_fields, matches) => {
+                let mut out = out();
+                write!(out, "{}", csv_header(false, time_fields, matches))?;
+                RowWriter::Csv(time_fields, matches, out)
+            }
+            RowFormat::Table(display_opts) => {
+                return StreamedTable::start(first, total, display_opts).map(RowWriter::Table);
+            }
+        };
+        for entry in first {
+            writer.write(entry)?;
+        }
+        Ok(writer)
+    }
+
+    fn write(&mut self, entry: &FileEntry) -> io::Result<()> {
+        match self {
+            RowWriter::Names(host, out) => writeln!(out, "{}", name_line(entry, *host)),
+            RowWriter::Csv(time_fields, matches, out) => {
+                write!(out, "{}", csv_row(None, entry, time_fields, *matches))
+            }
+            RowWriter::Table(table) => table.row(entry),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            RowWriter::Names(_, mut out) | RowWriter::Csv(_, _, mut out) => out.flush(),
+            RowWriter::Table(table) => table.finish(),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::types::{EntryType, FileEntry, Summary};
+
+pub fn build_summary(files: &[FileEntry], hidden: usize) -> Summary {
+    let mut summary = Summary::default();
+    for file in files {
+        summary.add(file);
+    }
+    summary.hidden = hidden;
+    summary
+}
+
+impl Summary {
+    /// Counts one more entry, so that streamed listings can be summed up
+    /// without keeping them.
+    pub fn add(&mut self, file: &FileEntry) {
+        // The `./` row from --show-cwd already contains every other entry, so
+        // counting it would inflate the totals.
+        if file.name == "./" || file.name.ends_with("/./") {
+            return;
+        }
+
+        match file.e_type {
+            EntryType::File => self.files += 1,
+            EntryType::Dir => self.directories += 1,
+            EntryType::Symlink => self.symlinks += 1,
+        }
+        // Hard links that another entry already counts add nothing
+        self.apparent_size += file.size - file.shared.apparent;
+        self.disk_size += file.disk_size - file.shared.allocated;
+        self.newest_modified = self.newest_modified.max(Some(file.modified));
+        self.oldest_modified = Some(match self.oldest_modified {
+            Some(oldest) => oldest.min(file.modified),
+            None => file.modified,
+        });
+        self.dedup_links += file.dedup_links + file.shared.links;
+    }
+}
@@ -1,6 +1,17 @@
-use serde::Serialize;
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
 use strum::Display;
-use tabled::Tabled;
+
+use crate::i18n::{Lang, Msg, tr};
+use crate::icons::IconTheme;
+use crate::search::ContentSearch;
+use crate::stat::Fields;
 
 #[derive(Debug, Clone, clap::ValueEnum)]
 pub enum TimeFormat {
@@ -11,6 +22,57 @@ pub enum TimeFormat {
     Rfc3339,
     Utf,
     Custom,
+    Relative,
+}
+
+#[derive(Debug, Clone)]
+pub enum Timezone {
+    Named(Tz),
+    Fixed(FixedOffset),
+    /// The system zone from chrono, used when neither `TZ` nor
+    /// `/etc/localtime` names a zone
+    Local,
+}
+
+impl Timezone {
+    pub fn format(&self, datetime: DateTime<Utc>, format: &str) -> String {
+        match self {
+            Timezone::Named(tz) => datetime.with_timezone(tz).format(format).to_string(),
+            Timezone::Fixed(offset) => datetime.with_timezone(offset).format(format).to_string(),
+            Timezone::Local => datetime.with_timezone(&Local).format(format).to_string(),
+        }
+    }
+
+    pub fn to_rfc3339(&self, datetime: DateTime<Utc>) -> String {
+        match self {
+            Timezone::Named(tz) => datetime.with_timezone(tz).to_rfc3339(),
+            Timezone::Fixed(offset) => datetime.with_timezone(offset).to_rfc3339(),
+            Timezone::Local => datetime.with_timezone(&Local).to_rfc3339(),
+        }
+    }
+
+    pub fn date_naive(&self, datetime: DateTime<Utc>) -> NaiveDate {
+        match self {
+            Timezone::Named(tz) => datetime.with_timezone(tz).date_naive(),
+            Timezone::Fixed(offset) => datetime.with_timezone(offset).date_naive(),
+            Timezone::Local => datetime.with_timezone(&Local).date_naive(),
+        }
+    }
+}
+
+/// strftime formats for files older and newer than six months
+#[derive(Debug, Clone)]
+pub struct TimeStyle {
+    pub old: String,
+    pub recent: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum TimeField {
+    Modified,
+    Accessed,
+    Changed,
+    Created,
 }
 
 #[derive(Debug, Clone, clap::ValueEnum)]
@@ -33,53 +95,384 @@ pub enum SortOrder {
     DirLast,
 }
 
-#[derive(Debug, Display, Serialize, Clone)]
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum SizeBar {
+    Max,
+    Total,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum Heatmap {
+    Age,
+    Size,
+    All,
+}
+
+#[derive(Debug, Clone)]
+pub struct Thresholds(pub Vec<u64>);
+
+#[derive(Debug, Clone, Default, clap::ValueEnum)]
+pub enum SizeUnits {
+    #[default]
+    Si,
+    Iec,
+    Bytes,
+}
+
+/// GNU-style `--block-size`: sizes are shown as a whole number of blocks,
+/// rounded up, followed by `suffix` (empty when a number was given).
+#[derive(Debug, Clone)]
+pub struct BlockSize {
+    pub bytes: u64,
+    pub suffix: String,
+    pub separators: bool,
+}
+
+/// How `--deep` uses the on-disk size cache.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheMode {
+    Use,
+    Refresh,
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, clap::ValueEnum)]
+pub enum SizeMode {
+    Apparent,
+    Disk,
+    Both,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SizeFormat {
+    pub units: SizeUnits,
+    pub block_size: Option<BlockSize>,
+    pub precision: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, clap::ValueEnum)]
+pub enum Hyperlink {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum GroupBy {
+    Ext,
+    Type,
+    Owner,
+    Day,
+    Calendar,
+    SizeBucket,
+}
+
+#[derive(Debug, Display, Serialize, Deserialize, Clone)]
 pub enum EntryType {
     File,
     Dir,
+    Symlink,
 }
 
+impl EntryType {
+    /// Name shown in the Type column; JSON keeps the English variant name.
+    pub fn label(&self) -> &'static str {
+        match self {
+            EntryType::File => tr(Msg::TypeFile),
+            EntryType::Dir => tr(Msg::TypeDir),
+            EntryType::Symlink => tr(Msg::TypeSymlink),
+        }
+    }
+}
+
+/// One listed entry with its metadata as read from disk. Nothing here is
+/// formatted: the renderers turn sizes, times and modes into text, so the
+/// same scan can be sorted, grouped and printed in any style.
 #[derive(Debug, Serialize, Clone)]
 pub struct FileEntry {
     #[serde(skip_serializing_if = "String::is_empty")]
     pub line_number: String,
     pub name: String,
     pub e_type: EntryType,
-    pub permissions: String,
-    pub size: String,
-    pub modified: String,
     #[serde(skip)]
-    pub raw_size: u64,
+    pub path: PathBuf,
+    /// File type and permission bits, as in `st_mode`
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Apparent size in bytes, of everything below for `--deep` directories
+    pub size: u64,
+    /// Allocated size in bytes
+    pub disk_size: u64,
+    /// Extra hard links left out of a `--deep` size
+    #[serde(skip_serializing_if = "is_zero")]
+    pub dedup_links: u64,
+    /// Part of a `--deep` size that another listed entry also counts
     #[serde(skip)]
-    pub raw_modified: std::time::SystemTime,
+    pub shared: SharedLinks,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sparse: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete: bool,
+    #[serde(serialize_with = "rfc3339::serialize")]
+    pub modified: SystemTime,
+    /// Only read when shown or sorted on
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "rfc3339::serialize_some"
+    )]
+    pub accessed: Option<SystemTime>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "rfc3339::serialize_some"
+    )]
+    pub changed: Option<SystemTime>,
+    /// Also None when the filesystem does not record birth times
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "rfc3339::serialize_some"
+    )]
+    pub created: Option<SystemTime>,
+    /// Set on files matched by `--contains`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_match: Option<ContentMatch>,
 }
 
-#[derive(Debug, Tabled)]
-pub struct TableRowWithLine {
-    #[tabled(rename = "#")]
-    pub line_number: String,
-    #[tabled(rename = "Name")]
-    pub name: String,
-    #[tabled(rename = "Type")]
-    pub e_type: String,
-    #[tabled(rename = "Permissions")]
-    pub permissions: String,
-    #[tabled(rename = "Size")]
-    pub size: String,
-    #[tabled(rename = "Modified Date")]
-    pub modified: String,
-}
-
-#[derive(Debug, Tabled)]
-pub struct TableRowNoLine {
-    #[tabled(rename = "Name")]
-    pub name: String,
-    #[tabled(rename = "Type")]
-    pub e_type: String,
-    #[tabled(rename = "Permissions")]
-    pub permissions: String,
-    #[tabled(rename = "Size")]
-    pub size: String,
-    #[tabled(rename = "Modified Date")]
-    pub modified: String,
+/// What `--contains` found in a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentMatch {
+    /// Number of matching lines
+    pub count: usize,
+    /// Line number of the first match, from 1
+    pub line: usize,
+    /// First matching line, trimmed
+    pub text: String,
+}
+
+impl FileEntry {
+    pub fn time(&self, field: &TimeField) -> Option<SystemTime> {
+        match field {
+            TimeField::Modified => Some(self.modified),
+            TimeField::Accessed => self.accessed,
+            TimeField::Changed => self.changed,
+            TimeField::Created => self.created,
+        }
+    }
+
+    /// Size shown in the Size column and used for sorting: the apparent
+    /// size, or the allocated size with `--size-mode disk|both`
+    pub fn listed_size(&self, size_mode: &SizeMode) -> u64 {
+        match size_mode {
+            SizeMode::Apparent => self.size,
+            SizeMode::Disk | SizeMode::Both => self.disk_size,
+        }
+    }
+}
+
+/// Timestamps in JSON are RFC 3339 in UTC, whatever `--time-format` says.
+mod rfc3339 {
+    use std::time::SystemTime;
+
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&crate::utils::rfc3339(*time))
+    }
+
+    pub fn serialize_some<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serialize(time, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EntrySize {
+    pub apparent: u64,
+    pub allocated: u64,
+    /// Extra hard links to an inode that was already counted
+    pub dedup_links: u64,
+    pub shared: SharedLinks,
+    /// Sizing stopped early because of Ctrl-C or `--timeout`
+    pub incomplete: bool,
+}
+
+impl std::ops::Add for EntrySize {
+    type Output = EntrySize;
+
+    fn add(self, other: EntrySize) -> EntrySize {
+        EntrySize {
+            apparent: self.apparent + other.apparent,
+            allocated: self.allocated + other.allocated,
+            dedup_links: self.dedup_links + other.dedup_links,
+            shared: self.shared + other.shared,
+            incomplete: self.incomplete || other.incomplete,
+        }
+    }
+}
+
+/// Hard-linked files that an entry counts in its size but that another
+/// listed entry counts as well. Each entry keeps its own size; the summary
+/// leaves these out so that the listing's total counts every inode once.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SharedLinks {
+    pub links: u64,
+    pub apparent: u64,
+    pub allocated: u64,
+}
+
+impl std::ops::Add for SharedLinks {
+    type Output = SharedLinks;
+
+    fn add(self, other: SharedLinks) -> SharedLinks {
+        SharedLinks {
+            links: self.links + other.links,
+            apparent: self.apparent + other.apparent,
+            allocated: self.allocated + other.allocated,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ScanResult {
+    pub hidden: usize,
+    /// Why `--deep` sizing stopped early, if it did
+    pub stopped: Option<StopReason>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Interrupted,
+    TimedOut,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub files: usize,
+    pub directories: usize,
+    pub symlinks: usize,
+    pub apparent_size: u64,
+    pub disk_size: u64,
+    #[serde(serialize_with = "rfc3339::serialize_some")]
+    pub newest_modified: Option<SystemTime>,
+    #[serde(serialize_with = "rfc3339::serialize_some")]
+    pub oldest_modified: Option<SystemTime>,
+    pub hidden: usize,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub dedup_links: u64,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+#[derive(Debug, Serialize)]
+pub struct Group {
+    pub group: String,
+    pub count: usize,
+    pub total_size: u64,
+    pub entries: Vec<FileEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    LineNumber,
+    Name,
+    Type,
+    Permissions,
+    Size,
+    DiskSize,
+    Usage,
+    Time(TimeField),
+    Matches,
+    FirstMatch,
+}
+
+impl Column {
+    pub fn header(&self) -> &'static str {
+        match self {
+            Column::LineNumber => "#",
+            Column::Name => tr(Msg::HeaderName),
+            Column::Type => tr(Msg::HeaderType),
+            Column::Permissions => tr(Msg::HeaderPermissions),
+            Column::Size => tr(Msg::HeaderSize),
+            Column::DiskSize => tr(Msg::HeaderDiskSize),
+            Column::Usage => tr(Msg::HeaderUsage),
+            Column::Time(TimeField::Modified) => tr(Msg::HeaderModified),
+            Column::Time(TimeField::Accessed) => tr(Msg::HeaderAccessed),
+            Column::Time(TimeField::Changed) => tr(Msg::HeaderChanged),
+            Column::Time(TimeField::Created) => tr(Msg::HeaderCreated),
+            Column::Matches => tr(Msg::HeaderMatches),
+            Column::FirstMatch => tr(Msg::HeaderFirstMatch),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub pattern: Option<String>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub show_line_numbers: bool,
+    pub deep: bool,
+    pub count_links: bool,
+    pub one_file_system: bool,
+    pub follow_symlinks: bool,
+    pub size_cache: CacheMode,
+    /// How long a cached directory size is trusted
+    pub cache_max_age: Duration,
+    /// Time budget for `--deep` sizing
+    pub timeout: Option<Duration>,
+    pub time_fields: Vec<TimeField>,
+    /// Metadata to ask for beyond what every listing needs
+    pub stat_fields: Fields,
+    pub show_cwd: bool,
+    /// Only keep files whose contents match, for `--contains`
+    pub search: Option<ContentSearch>,
+}
+
+/// How sizes, times and permissions are written out by the renderers.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    pub octal_perms: bool,
+    pub owner_perms: bool,
+    pub time_format: TimeFormat,
+    pub timezone: Timezone,
+    pub custom_time_format: String,
+    pub time_style: Option<TimeStyle>,
+    pub toggle_clock: bool,
+    pub relative_precision: usize,
+    pub time_locale: Lang,
+    pub size_format: SizeFormat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameFit {
+    Truncate,
+    Wrap,
+    Full,
+}
+
+#[derive(Debug, Clone)]
+pub struct DisplayOptions {
+    pub show_line_numbers: bool,
+    pub name_fit: NameFit,
+    pub time_fields: Vec<TimeField>,
+    /// Size that a full bar in the Usage column stands for
+    pub size_bar: Option<u64>,
+    /// Ascending age limits in seconds for coloring the Modified column
+    pub age_heatmap: Option<Vec<u64>>,
+    /// Ascending size limits in bytes for coloring the Size column
+    pub size_heatmap: Option<Vec<u64>>,
+    pub size_mode: SizeMode,
+    pub format: FormatOptions,
+    pub icons: Option<IconTheme>,
+    /// Host name for `file://` links around names, when hyperlinks are on
+    pub hyperlink_host: Option<String>,
+    /// Add the `--contains` match columns
+    pub show_matches: bool,
 }
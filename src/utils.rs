@@ -1,84 +1,535 @@
-use std::fs;
+use std::{fs, path::Path, time::SystemTime};
 
-use chrono::{DateTime, Local, Utc};
-use chrono_tz::{Tz, UTC};
+use chrono::{
+    DateTime, FixedOffset, SecondsFormat, Utc,
+    format::{Item, StrftimeItems},
+};
+use chrono_tz::Tz;
 use owo_colors::OwoColorize;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::types::TimeFormat;
+use crate::i18n::{self, Lang, Msg, tr};
+use crate::types::{
+    BlockSize, FormatOptions, SizeFormat, SizeUnits, Thresholds, TimeFormat, TimeStyle, Timezone,
+};
 
-pub fn parse_timezone(timezone_str: &str) -> Tz {
-    match timezone_str.parse::<Tz>() {
-        Ok(tz) => tz,
-        Err(_) => {
-            eprintln!(
-                "{}: '{}'. Using UTC instead.",
-                "Invalid timezone".red(),
-                timezone_str
-            );
-            UTC
+const SIX_MONTHS_SECS: u64 = 31_556_952 / 2;
+
+/// Resolves `--timezone`, falling back to the `TZ` environment variable,
+/// then the zone `/etc/localtime` points to, then the system offset.
+pub fn parse_timezone(timezone_str: Option<&str>) -> Timezone {
+    if let Some(timezone_str) = timezone_str {
+        if let Some(timezone) = parse_timezone_name(timezone_str) {
+            return timezone;
+        }
+        eprintln!(
+            "{}",
+            tr(Msg::InvalidTimezone).replace("{}", timezone_str).red()
+        );
+    }
+
+    if let Some(timezone) = std::env::var("TZ")
+        .ok()
+        .and_then(|tz| parse_timezone_name(tz.trim_start_matches(':')))
+    {
+        return timezone;
+    }
+
+    fs::read_link("/etc/localtime")
+        .ok()
+        .and_then(|target| {
+            let target = target.to_string_lossy().into_owned();
+            let (_, name) = target.split_once("zoneinfo/")?;
+            name.parse::<Tz>().ok()
+        })
+        .map(Timezone::Named)
+        .unwrap_or(Timezone::Local)
+}
+
+/// Accepts IANA names (`Asia/Jakarta`) and fixed offsets (`+05:30`,
+/// `-0800`, `+07`).
+fn parse_timezone_name(name: &str) -> Option<Timezone> {
+    if let Ok(tz) = name.parse::<Tz>() {
+        return Some(Timezone::Named(tz));
+    }
+
+    let sign = match name.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = name[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Timezone::Fixed)
+}
+
+/// Width of the terminal the listing is printed to.
+#[cfg(unix)]
+pub fn terminal_width() -> Option<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    fd_width(std::io::stdout().as_raw_fd())
+}
+
+/// Width of the terminal stderr goes to, for the progress line, which stays
+/// on the terminal when the listing is piped.
+#[cfg(unix)]
+pub fn stderr_width() -> Option<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    fd_width(std::io::stderr().as_raw_fd())
+}
+
+#[cfg(unix)]
+fn fd_width(fd: libc::c_int) -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+        return Some(size.ws_col as usize);
+    }
+
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|cols| cols.trim().parse().ok())
+        .filter(|cols| *cols > 0)
+}
+
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Like `display_width`, but skips terminal escape sequences such as the
+/// OSC 8 links from `hyperlink`.
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            width += UnicodeWidthChar::width(c).unwrap_or(0);
+            continue;
+        }
+        match chars.next() {
+            // CSI, ended by a byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC, ended by BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
         }
     }
+    width
 }
 
-pub fn format_size(size: u64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB"];
-    const THRESHOLD: f64 = 1000.0;
+/// Shortens `name` to at most `max_width` terminal columns by replacing its
+/// middle with an ellipsis, keeping the extension and any trailing `/` intact.
+pub fn truncate_middle(name: &str, max_width: usize) -> String {
+    const ELLIPSIS: char = '…';
+
+    if display_width(name) <= max_width {
+        return name.to_string();
+    }
+
+    let (body, slash) = match name.strip_suffix('/') {
+        Some(body) => (body, "/"),
+        None => (name, ""),
+    };
+    let (stem, ext) = match body.rfind('.') {
+        Some(dot) if dot > 0 && display_width(&body[dot..]) <= max_width / 3 => {
+            (&body[..dot], &body[dot..])
+        }
+        _ => (body, ""),
+    };
+
+    let suffix = format!("{}{}", ext, slash);
+    let budget = max_width.saturating_sub(display_width(&suffix) + 1);
+    let head_budget = budget.div_ceil(2);
+    let tail_budget = budget - head_budget;
+
+    let mut head = String::new();
+    let mut used = 0;
+    for ch in stem.chars() {
+        let width = ch.width().unwrap_or(0);
+        if used + width > head_budget {
+            break;
+        }
+        head.push(ch);
+        used += width;
+    }
+
+    let mut tail = Vec::new();
+    let mut used = 0;
+    for ch in stem.chars().rev() {
+        let width = ch.width().unwrap_or(0);
+        if used + width > tail_budget {
+            break;
+        }
+        tail.push(ch);
+        used += width;
+    }
+    let tail: String = tail.into_iter().rev().collect();
+
+    format!("{}{}{}{}", head, ELLIPSIS, tail, suffix)
+}
+
+/// Shortens `text` to at most `max_width` terminal columns by cutting its
+/// end and appending an ellipsis.
+pub fn truncate_end(text: &str, max_width: usize) -> String {
+    if display_width(text) <= max_width {
+        return text.to_string();
+    }
+
+    let mut shortened = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        let width = ch.width().unwrap_or(0);
+        if used + width + 1 > max_width {
+            break;
+        }
+        shortened.push(ch);
+        used += width;
+    }
+    shortened.push('…');
+    shortened
+}
+
+/// Parses durations such as `90s`, `15m`, `1h`, `2d` or `1w` into seconds.
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", text))?;
+
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        "w" => 604_800,
+        _ => return Err(format!("unknown duration unit in '{}'", text)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("duration '{}' is too long", text))
+}
+
+/// Parses sizes such as `512`, `4KB`, `1.5MB`, `2G` or `2GiB` into bytes,
+/// with the units of `unit_bytes`.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", text))?;
+
+    let multiplier =
+        unit_bytes(unit.trim()).ok_or_else(|| format!("unknown size unit in '{}'", text))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Bytes in a size unit, as in GNU coreutils: `KB`, `MB` and so on are
+/// powers of 1000, while `K`, `KiB`, `M`, `MiB` and so on are powers of
+/// 1024. Case is ignored.
+fn unit_bytes(unit: &str) -> Option<u64> {
+    let unit = unit.to_ascii_uppercase();
+    if unit.is_empty() || unit == "B" {
+        return Some(1);
+    }
+    let mut chars = unit.chars();
+    let power = "KMGTPE".find(chars.next()?)? as u32 + 1;
+    match chars.as_str() {
+        "" | "IB" => Some(1024u64.pow(power)),
+        "B" => Some(1000u64.pow(power)),
+        _ => None,
+    }
+}
+
+pub fn parse_age_thresholds(text: &str) -> Result<Thresholds, String> {
+    parse_thresholds(text, parse_duration)
+}
+
+pub fn parse_size_thresholds(text: &str) -> Result<Thresholds, String> {
+    parse_thresholds(text, parse_size)
+}
+
+/// Parses exactly four comma-separated, strictly ascending limits.
+fn parse_thresholds(
+    text: &str,
+    parse_one: fn(&str) -> Result<u64, String>,
+) -> Result<Thresholds, String> {
+    let limits = text
+        .split(',')
+        .map(parse_one)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if limits.len() != 4 {
+        return Err(format!("expected 4 limits, got {}", limits.len()));
+    }
+    if limits.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("limits must be in ascending order".to_string());
+    }
+    Ok(Thresholds(limits))
+}
+
+pub fn format_size(size: u64, format: &SizeFormat) -> String {
+    if let Some(block) = &format.block_size {
+        let blocks = size.div_ceil(block.bytes);
+        let number = if block.separators {
+            group_thousands(blocks)
+        } else {
+            blocks.to_string()
+        };
+        return format!("{}{}", number, block.suffix);
+    }
+
+    let (threshold, units): (f64, &[&str]) = match format.units {
+        SizeUnits::Si => (1000.0, &["B", "KB", "MB", "GB", "TB", "PB"]),
+        SizeUnits::Iec => (1024.0, &["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+        SizeUnits::Bytes => return format!("{} B", group_thousands(size)),
+    };
 
     if size == 0 {
         return "   0 B".to_string();
     }
 
-    let size_f = size as f64;
-    let unit_index = (size_f.log10() / THRESHOLD.log10()).floor() as usize;
-    let unit_index = unit_index.min(UNITS.len() - 1);
+    let mut scaled_size = size as f64;
+    let mut unit_index = 0;
+    while scaled_size >= threshold && unit_index < units.len() - 1 {
+        scaled_size /= threshold;
+        unit_index += 1;
+    }
 
     if unit_index == 0 {
         format!("{:>4} B", size)
     } else {
-        let scaled_size = size_f / THRESHOLD.powi(unit_index as i32);
-        let formatted_number = if scaled_size >= 100.0 {
-            format!("{:.0}", scaled_size)
-        } else if scaled_size >= 10.0 {
-            format!("{:.1}", scaled_size)
-        } else {
-            format!("{:.2}", scaled_size)
+        let precision = match format.precision {
+            Some(precision) => precision,
+            None if scaled_size >= 100.0 => 0,
+            None if scaled_size >= 10.0 => 1,
+            None => 2,
         };
+        let formatted_number = format_decimal(scaled_size, precision);
 
-        format!("{:>4} {}", formatted_number, UNITS[unit_index])
+        format!("{:>4} {}", formatted_number, units[unit_index])
     }
 }
 
-pub fn format_datetime(
-    system_time: std::time::SystemTime,
-    time_format: &TimeFormat,
-    timezone: &Tz,
-    custom_format: &str,
-    toggle_clock: bool,
-) -> String {
-    let datetime: DateTime<Utc> = system_time.into();
-    match time_format {
-        TimeFormat::Utc => {
-            let tz_time = datetime.with_timezone(&Utc);
-            if toggle_clock {
-                format!("{}", tz_time.format("%a %b %e %Y %H:%M:%S"))
-            } else {
-                format!("{}", tz_time.format("%a %b %e %Y"))
+/// `value` rounded to `precision` digits, with the decimal separator of the
+/// message language.
+pub fn format_decimal(value: f64, precision: usize) -> String {
+    format!("{:.*}", precision, value).replace('.', &i18n::decimal_separator().to_string())
+}
+
+/// 1234567 -> "1,234,567", using the separator of the message language.
+fn group_thousands(number: u64) -> String {
+    let digits = number.to_string();
+    let separator = i18n::thousands_separator();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Parses GNU `ls --block-size` values: `K`, `M`, `KB`, `MiB`, `1M`, `512`.
+/// A bare unit is printed after each size, a leading `'` adds digit group
+/// separators.
+pub fn parse_block_size(text: &str) -> Result<BlockSize, String> {
+    let (separators, rest) = match text.strip_prefix('\'') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let split = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let (number, unit) = rest.split_at(split);
+
+    let count: u64 = if number.is_empty() {
+        1
+    } else {
+        number
+            .parse()
+            .map_err(|_| format!("invalid block size '{}'", text))?
+    };
+
+    let multiplier =
+        unit_bytes(unit).ok_or_else(|| format!("unknown block size unit in '{}'", text))?;
+
+    let bytes = count
+        .checked_mul(multiplier)
+        .filter(|bytes| *bytes > 0)
+        .ok_or_else(|| format!("invalid block size '{}'", text))?;
+
+    Ok(BlockSize {
+        bytes,
+        suffix: if number.is_empty() {
+            unit.to_string()
+        } else {
+            String::new()
+        },
+        separators,
+    })
+}
+
+/// Checks a strftime-style format up front, since chrono panics on invalid
+/// specifiers only once the date is displayed. GNU's `%N` is accepted as an
+/// alias for nanoseconds.
+pub fn parse_time_format(format: &str) -> Result<String, String> {
+    // Only real specifiers are translated, so `%%N` stays a literal `%N`
+    let mut translated = String::with_capacity(format.len());
+    let mut rest = format;
+    while let Some(start) = rest.find('%') {
+        translated.push_str(&rest[..start]);
+        let spec = &rest[start..];
+        let len = specifier_len(spec);
+        match spec[..len].strip_suffix('N') {
+            Some(modifiers) => {
+                translated.push_str(modifiers);
+                translated.push('f');
             }
+            None => translated.push_str(&spec[..len]),
         }
-        TimeFormat::Utf => {
-            let tz_time = datetime.with_timezone(timezone);
-            if toggle_clock {
-                format!("{}", tz_time.format("%a %b %e %Y %H:%M:%S"))
+        rest = &spec[len..];
+    }
+    translated.push_str(rest);
+    let format = translated;
+    if !StrftimeItems::new(&format).any(|item| item == Item::Error) {
+        return Ok(format);
+    }
+
+    // Point at the first specifier chrono rejects, skipping escaped `%%`
+    let mut rest = format.as_str();
+    while let Some(start) = rest.find('%') {
+        let spec = &rest[start..];
+        let len = specifier_len(spec);
+        if StrftimeItems::new(&spec[..len]).any(|item| item == Item::Error) {
+            return Err(format!(
+                "unsupported specifier '{}' in '{}'",
+                &spec[..len],
+                format
+            ));
+        }
+        rest = &spec[len..];
+    }
+    Err(format!("invalid time format '{}'", format))
+}
+
+/// Length of the specifier `spec` starts with: its `%`, any flags, width or
+/// colons, and the letter, `%` or `+` that ends it.
+fn specifier_len(spec: &str) -> usize {
+    spec.char_indices()
+        .skip(1)
+        .find(|(_, c)| c.is_ascii_alphabetic() || matches!(c, '%' | '+'))
+        .map(|(index, c)| index + c.len_utf8())
+        .unwrap_or(spec.len())
+}
+
+/// Parses a GNU `ls --time-style` value: a preset name or `+FORMAT`, where
+/// `+OLD\nRECENT` picks a second format for files modified within the last
+/// six months.
+pub fn parse_time_style(style: &str) -> Result<TimeStyle, String> {
+    let style = style.strip_prefix("posix-").unwrap_or(style);
+    let (old, recent) = match style {
+        "full-iso" => ("%Y-%m-%d %H:%M:%S%.9f %z", "%Y-%m-%d %H:%M:%S%.9f %z"),
+        "long-iso" => ("%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M"),
+        "iso" => ("%Y-%m-%d ", "%m-%d %H:%M"),
+        "locale" => ("%b %e  %Y", "%b %e %H:%M"),
+        _ => {
+            let Some(format) = style.strip_prefix('+') else {
+                return Err(format!(
+                    "unknown time style '{}' (expected full-iso, long-iso, iso, locale or +FORMAT)",
+                    style
+                ));
+            };
+            let (old, recent) = format.split_once('\n').unwrap_or((format, format));
+            return Ok(TimeStyle {
+                old: parse_time_format(old)?,
+                recent: parse_time_format(recent)?,
+            });
+        }
+    };
+    Ok(TimeStyle {
+        old: old.to_string(),
+        recent: recent.to_string(),
+    })
+}
+
+/// RFC 3339 in UTC, as used for timestamps in JSON and CSV.
+pub fn rfc3339(time: SystemTime) -> String {
+    let datetime: DateTime<Utc> = time.into();
+    datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+pub fn format_datetime(system_time: SystemTime, opts: &FormatOptions) -> String {
+    let timezone = &opts.timezone;
+    let toggle_clock = opts.toggle_clock;
+    let datetime: DateTime<Utc> = system_time.into();
+    let lang = opts.time_locale;
+    // Weekday and month names follow LC_TIME, everything else is left to chrono
+    let render = |format: &str| {
+        let format = i18n::localize_format(format, timezone.date_naive(datetime), lang);
+        timezone.format(datetime, &format)
+    };
+
+    if let Some(style) = &opts.time_style {
+        // Same cut-off as GNU ls: recent means the past six months, not the future
+        let recent = SystemTime::now()
+            .duration_since(system_time)
+            .is_ok_and(|age| age.as_secs() < SIX_MONTHS_SECS);
+        return render(if recent { &style.recent } else { &style.old });
+    }
+
+    match opts.time_format {
+        TimeFormat::Utc => {
+            let format = if toggle_clock {
+                "%a %b %e %Y %H:%M:%S"
             } else {
-                format!("{}", tz_time.format("%a %b %e %Y"))
-            }
+                "%a %b %e %Y"
+            };
+            let format = i18n::localize_format(format, datetime.date_naive(), lang);
+            datetime.format(&format).to_string()
         }
-        TimeFormat::Local => {
-            let tz_time = datetime.with_timezone(&Local);
+        TimeFormat::Utf | TimeFormat::Local => {
             if toggle_clock {
-                format!("{}", tz_time.format("%a %b %e %Y %H:%M:%S"))
+                render("%a %b %e %Y %H:%M:%S")
             } else {
-                format!("{}", tz_time.format("%a %b %e %Y"))
+                render("%a %b %e %Y")
             }
         }
         TimeFormat::Unix => {
@@ -86,7 +537,7 @@ pub fn format_datetime(
                 format!(
                     "{} ({})",
                     datetime.timestamp(),
-                    datetime.format("%a %b %e %Y %H:%M:%S")
+                    render("%a %b %e %Y %H:%M:%S")
                 )
             } else {
                 datetime.timestamp().to_string()
@@ -94,30 +545,76 @@ pub fn format_datetime(
         }
         TimeFormat::Iso8601 => {
             if toggle_clock {
-                format!("{}", datetime.format("%Y-%m-%dT%H:%M:%SZ"))
+                timezone.format(datetime, "%Y-%m-%dT%H:%M:%S%:z")
             } else {
-                format!("{}", datetime.format("%Y-%m-%d"))
+                timezone.format(datetime, "%Y-%m-%d")
             }
         }
         TimeFormat::Rfc3339 => {
             if toggle_clock {
-                datetime.to_rfc3339()
+                timezone.to_rfc3339(datetime)
             } else {
-                format!("{}", datetime.format("%Y-%m-%d"))
+                timezone.format(datetime, "%Y-%m-%d")
             }
         }
-        TimeFormat::Custom => {
-            let tz_time = datetime.with_timezone(timezone);
-            format!("{}", tz_time.format(custom_format))
+        TimeFormat::Custom => render(&opts.custom_time_format),
+        TimeFormat::Relative => format_relative(
+            system_time,
+            SystemTime::now(),
+            opts.relative_precision,
+            lang,
+        ),
+    }
+}
+
+/// Describes `time` relative to `now` ("5 min ago", "in 2 days"), using up
+/// to `precision` units.
+pub fn format_relative(time: SystemTime, now: SystemTime, precision: usize, lang: Lang) -> String {
+    const UNIT_SECS: [u64; 7] = [31_536_000, 2_592_000, 604_800, 86_400, 3_600, 60, 1];
+    let words = i18n::relative_words(lang);
+
+    let (mut secs, future) = match now.duration_since(time) {
+        Ok(elapsed) => (elapsed.as_secs(), false),
+        Err(err) => (err.duration().as_secs(), true),
+    };
+
+    if secs < 10 {
+        return words.just_now.to_string();
+    }
+    if precision <= 1 && (86_400..172_800).contains(&secs) {
+        return if future {
+            words.tomorrow
+        } else {
+            words.yesterday
+        }
+        .to_string();
+    }
+
+    let mut parts = Vec::new();
+    for (unit_secs, (singular, plural)) in UNIT_SECS.iter().zip(words.units) {
+        if parts.len() == precision.max(1) {
+            break;
+        }
+        let count = secs / unit_secs;
+        if count > 0 {
+            parts.push(format!(
+                "{} {}",
+                count,
+                if count == 1 { singular } else { plural }
+            ));
+            secs %= unit_secs;
+        } else if !parts.is_empty() {
+            // Keep adjacent units only, e.g. "1 hour 5 min" but not "1 day 3 sec"
+            break;
         }
     }
+
+    let template = if future { words.in_future } else { words.ago };
+    template.replace("{}", &parts.join(" "))
 }
 
 #[cfg(unix)]
-pub fn format_permissions_rwx(metadata: &fs::Metadata) -> String {
-    use std::os::unix::fs::PermissionsExt;
-
-    let mode = metadata.permissions().mode();
+pub fn format_permissions_rwx(mode: u32) -> String {
     let permissions = mode & 0o777;
     let mut perm_str = String::with_capacity(9);
 
@@ -140,22 +637,92 @@ pub fn format_permissions_rwx(metadata: &fs::Metadata) -> String {
 }
 
 #[cfg(unix)]
-pub fn format_permissions_octal(metadata: &fs::Metadata) -> String {
-    use std::os::unix::fs::PermissionsExt;
-
-    let mode = metadata.permissions().mode();
+pub fn format_permissions_octal(mode: u32) -> String {
     let permissions = mode & 0o777;
     format!("{:03o}", permissions)
 }
 
 #[cfg(unix)]
-pub fn format_permissions_owner_type(metadata: &fs::Metadata) -> String {
-    use std::os::unix::fs::MetadataExt;
-    let uid = metadata.uid();
+pub fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return String::new();
+    }
+    unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Wraps `text` in an OSC 8 escape sequence linking to `path` on `host`.
+#[cfg(unix)]
+pub fn hyperlink(text: &str, path: &Path, host: &str) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut url = format!("file://{}", host);
+    for &byte in absolute.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
 
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+#[cfg(unix)]
+pub fn owner_name(uid: u32) -> String {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+    let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return uid.to_string();
+    }
+
+    unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(unix)]
+pub fn format_permissions_owner_type(uid: u32) -> String {
     if uid == 0 {
         "   Root".to_string()
     } else {
         "   User".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_overflow_is_an_error() {
+        assert_eq!(parse_duration("90m").unwrap(), 5_400);
+        assert!(parse_duration("99999999999999999h").is_err());
+        assert!(parse_duration("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn time_format_translates_gnu_nanoseconds() {
+        assert_eq!(parse_time_format("%H:%M:%S.%N").unwrap(), "%H:%M:%S.%f");
+        assert_eq!(parse_time_format("%3N").unwrap(), "%3f");
+    }
+
+    #[test]
+    fn time_format_keeps_escaped_percent_n() {
+        assert_eq!(parse_time_format("%%N").unwrap(), "%%N");
+        assert_eq!(parse_time_format("100%%N %N").unwrap(), "100%%N %f");
+        assert_eq!(parse_time_format("%%%N").unwrap(), "%%%f");
+    }
+
+    #[test]
+    fn time_format_rejects_unknown_specifiers() {
+        let error = parse_time_format("%Y %Q").unwrap_err();
+        assert!(error.contains("'%Q'"), "{error}");
+    }
+}